@@ -0,0 +1,224 @@
+use std::fmt;
+use std::sync::LazyLock;
+use regex::Regex;
+
+static POSITION_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"line (\d+),? column (\d+)").unwrap()
+});
+
+/// Position in a YAML source, both 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}:{}", file, self.line, self.column),
+            None => write!(f, "line {}, column {}", self.line, self.column),
+        }
+    }
+}
+
+/// Where an error happened: the chain of component calls and the position of the key or value
+/// that caused it, or of the innermost component when it cannot be found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub call_stack: Vec<String>,
+    pub location: Option<Location>,
+}
+
+impl Trace {
+    pub fn is_empty(&self) -> bool {
+        self.call_stack.is_empty() && self.location.is_none()
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.call_stack.is_empty(), &self.location) {
+            (true, None) => Ok(()),
+            (true, Some(location)) => write!(f, " (at {})", location),
+            (false, None) => write!(f, " (in {})", self.call_stack.join(" > ")),
+            (false, Some(location)) => {
+                write!(f, " (in {} at {})", self.call_stack.join(" > "), location)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The YAML could not be read or does not have the expected shape
+    Syntax { message: String, trace: Trace },
+    /// Reading a components file failed
+    Io { message: String, trace: Trace },
+    /// What strict mode found unresolved: components that were referenced but are not defined,
+    /// and props still unbound after every component was applied
+    Unresolved { components: Vec<String>, props: Vec<String>, trace: Trace },
+    /// A component ended up calling itself without end
+    Recursion { path: Vec<String>, message: String, trace: Trace },
+    /// A Rust function component failed
+    Function { name: String, message: String, trace: Trace },
+    /// A `${...}` expression could not be parsed or evaluated
+    Expression { expression: String, message: String, trace: Trace },
+    /// A `$import` could not be resolved
+    Import { file: String, message: String, trace: Trace },
+    /// An entry point could not be mounted into the HTML shell
+    Shell { selector: String, message: String, trace: Trace },
+    /// Props given to a component do not match its `props` schema
+    Props { component: String, message: String, trace: Trace },
+}
+
+impl Error {
+    pub fn syntax(message: impl Into<String>) -> Error {
+        Error::Syntax { message: message.into(), trace: Trace::default() }
+    }
+
+    pub fn function(name: &str, message: impl Into<String>) -> Error {
+        Error::Function { name: name.into(), message: message.into(), trace: Trace::default() }
+    }
+
+    pub fn expression(expression: &str, message: impl Into<String>) -> Error {
+        Error::Expression {
+            expression: expression.into(),
+            message: message.into(),
+            trace: Trace::default(),
+        }
+    }
+
+    pub fn import(file: &str, message: impl Into<String>) -> Error {
+        Error::Import { file: file.into(), message: message.into(), trace: Trace::default() }
+    }
+
+    pub fn shell(selector: &str, message: impl Into<String>) -> Error {
+        Error::Shell { selector: selector.into(), message: message.into(), trace: Trace::default() }
+    }
+
+    pub fn props(component: &str, message: impl Into<String>) -> Error {
+        Error::Props { component: component.into(), message: message.into(), trace: Trace::default() }
+    }
+
+    pub fn trace(&self) -> &Trace {
+        match self {
+            Error::Syntax { trace, .. }
+            | Error::Io { trace, .. }
+            | Error::Unresolved { trace, .. }
+            | Error::Recursion { trace, .. }
+            | Error::Function { trace, .. }
+            | Error::Expression { trace, .. }
+            | Error::Import { trace, .. }
+            | Error::Shell { trace, .. }
+            | Error::Props { trace, .. } => trace,
+        }
+    }
+
+    fn trace_mut(&mut self) -> &mut Trace {
+        match self {
+            Error::Syntax { trace, .. }
+            | Error::Io { trace, .. }
+            | Error::Unresolved { trace, .. }
+            | Error::Recursion { trace, .. }
+            | Error::Function { trace, .. }
+            | Error::Expression { trace, .. }
+            | Error::Import { trace, .. }
+            | Error::Shell { trace, .. }
+            | Error::Props { trace, .. } => trace,
+        }
+    }
+
+    pub fn call_stack(&self) -> &[String] {
+        &self.trace().call_stack
+    }
+
+    pub fn location(&self) -> Option<&Location> {
+        self.trace().location.as_ref()
+    }
+
+    /// Text of the key or value that caused the error, searched in the sources to locate it
+    pub(crate) fn culprit(&self) -> Option<String> {
+        match self {
+            Error::Unresolved { components, props, .. } => components
+                .first()
+                .cloned()
+                .or_else(|| props.first().map(|name| format!("${}", name))),
+            Error::Recursion { path, .. } => path.last().cloned(),
+            Error::Function { name, .. } => Some(name.clone()),
+            Error::Expression { expression, .. } => Some(expression.clone()),
+            Error::Props { component, .. } => Some(component.clone()),
+            Error::Syntax { .. } | Error::Io { .. } | Error::Import { .. } | Error::Shell { .. } => None,
+        }
+    }
+
+    /// Sets the trace unless a deeper call already did
+    pub fn with_trace(mut self, trace: Trace) -> Error {
+        if self.trace().is_empty() {
+            *self.trace_mut() = trace;
+        }
+        self
+    }
+
+    /// Sets the file of the location, used once the failing file is known
+    pub fn in_file(mut self, file: &str) -> Error {
+        if let Some(location) = &mut self.trace_mut().location
+            && location.file.is_none() {
+            location.file = Some(file.into());
+        }
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Syntax { message, .. } => write!(f, "invalid YAML: {}", message)?,
+            Error::Io { message, .. } => write!(f, "{}", message)?,
+            Error::Unresolved { components, props, .. } => {
+                if !components.is_empty() {
+                    let plural = if components.len() > 1 { "s" } else { "" };
+                    write!(f, "unknown component{} `{}`", plural, components.join("`, `"))?;
+                }
+                if !components.is_empty() && !props.is_empty() {
+                    f.write_str(" and ")?;
+                }
+                if !props.is_empty() {
+                    write!(f, "unbound prop{} `${}`", if props.len() > 1 { "s" } else { "" }, props.join("`, `$"))?;
+                }
+            }
+            Error::Recursion { path, message, .. } => {
+                write!(f, "{}: {}", message, path.join(" > "))?
+            }
+            Error::Function { name, message, .. } => write!(f, "function `{}` failed: {}", name, message)?,
+            Error::Expression { expression, message, .. } => {
+                write!(f, "expression `{}` failed: {}", expression, message)?
+            }
+            Error::Import { file, message, .. } => write!(f, "cannot import `{}`: {}", file, message)?,
+            Error::Shell { selector, message, .. } => write!(f, "cannot mount `{}`: {}", selector, message)?,
+            Error::Props { component, message, .. } => write!(f, "invalid props for `{}`: {}", component, message)?,
+        }
+        write!(f, "{}", self.trace())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<rust_yaml::Error> for Error {
+    fn from(error: rust_yaml::Error) -> Self {
+        let message = error.to_string();
+        let location = POSITION_RE.captures(&message).map(|caps| Location {
+            file: None,
+            line: caps[1].parse().unwrap_or_default(),
+            column: caps[2].parse().unwrap_or_default(),
+        });
+        Error::Syntax { message, trace: Trace { call_stack: vec![], location } }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io { message: error.to_string(), trace: Trace::default() }
+    }
+}
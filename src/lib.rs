@@ -1,2 +1,8 @@
+pub mod error;
 pub mod parser;
-pub use parser::render;
\ No newline at end of file
+#[cfg(feature = "server")]
+pub mod server;
+pub use error::Error;
+pub use parser::{node, render};
+#[cfg(feature = "server")]
+pub use server::Server;
@@ -1,19 +1,289 @@
-use std::io::Read;
-use rust_yaml::Yaml;
+use std::io::{Read, Write};
+use std::process::ExitCode;
+use indexmap::IndexMap;
+use htymlx::parser::{parse_scalar, Parser};
+use htymlx::render::{document_with, html_with, Options};
+use rust_yaml::{Value, Yaml};
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let yaml = Yaml::new();
+const USAGE: &str = "Usage:
+    htymlx render <components.yml> <component> [options]
+    htymlx render <components.yml> --shell <index.html> [options]
+    htymlx serve <components.yml> [options]
 
-    // Parse YAML from a string
-    let mut yaml_content = String::new();
-    std::io::stdin().read_to_string(&mut yaml_content)?;
+Options:
+    --props <file>        Props as a YAML/JSON file, `-` reads them from stdin
+    --prop <key>=<value>  Single prop, can be repeated and overrides --props
+    -o, --output <file>   Write the HTML to a file instead of stdout
+    --document            Render a full HTML document with the `head:` entries hoisted,
+                          not available with --shell which is already a document
+    --pretty              Indent the rendered component, not available with --shell
+    --shell <file>        Mount the `$(<selector>)` entry points into an HTML file
+    --address <address>   Address `serve` listens on, 127.0.0.1:8080 by default
+    --sqlite <file>       SQLite database used by `from: sql` components
+    --strict              Fail on unknown components and unbound props
+    -h, --help            Show this message
 
-    let parsed = yaml.load_str(yaml_content.as_str())?;
-    println!("Parsed: {:?}", parsed.to_string());
+<components.yml> can also be a directory with a component per file.";
 
-    // Dump back to YAML
-    let output = yaml.dump_str(&parsed)?;
-    println!("Output:\n{}", output);
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
 
-    Ok(())
+struct RenderArgs {
+    components: String,
+    target: Target,
+    props_file: Option<String>,
+    props: Vec<(String, String)>,
+    output: Option<String>,
+    document: bool,
+    pretty: bool,
+    sqlite: Option<String>,
+    strict: bool,
+}
+
+/// What `render` outputs: a single component or an HTML shell with the entry points mounted
+enum Target {
+    Component(String),
+    Shell(String),
+}
+
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+struct ServeArgs {
+    components: String,
+    address: String,
+    shell: Option<String>,
+    sqlite: Option<String>,
+    strict: bool,
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("render") => parse_render_args(&args[1..]).and_then(|args| render(&args).map_err(Failure::Error)),
+        Some("serve") => parse_serve_args(&args[1..]).and_then(|args| serve(&args).map_err(Failure::Error)),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Some(command) => Err(Failure::Usage(format!("unknown command `{}`", command))),
+        None => Err(Failure::Usage("missing command".to_string())),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(message)) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            ExitCode::from(2)
+        }
+        Err(Failure::Error(message)) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+enum Failure {
+    Usage(String),
+    Error(String),
+}
+
+fn parse_render_args(args: &[String]) -> Result<RenderArgs, Failure> {
+    let mut positional = Vec::new();
+    let mut props_file = None;
+    let mut props = Vec::new();
+    let mut output = None;
+    let mut document = false;
+    let mut pretty = false;
+    let mut shell = None;
+    let mut sqlite = None;
+    let mut strict = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| Failure::Usage(format!("missing value for `{}`", name)))
+        };
+        match arg.as_str() {
+            "--props" => props_file = Some(value(arg)?),
+            "--prop" => {
+                let prop = value(arg)?;
+                let (key, value) = prop
+                    .split_once('=')
+                    .ok_or_else(|| Failure::Usage(format!("expected `key=value`, got `{}`", prop)))?;
+                props.push((key.to_string(), value.to_string()));
+            }
+            "-o" | "--output" => output = Some(value(arg)?),
+            "--document" => document = true,
+            "--pretty" => pretty = true,
+            "--shell" => shell = Some(value(arg)?),
+            "--sqlite" => sqlite = Some(value(arg)?),
+            "--strict" => strict = true,
+            arg if arg.starts_with('-') && arg != "-" => {
+                return Err(Failure::Usage(format!("unknown option `{}`", arg)));
+            }
+            arg => positional.push(arg.to_string()),
+        }
+    }
+    let (components, target) = match shell {
+        Some(shell) => {
+            let [components] = <[String; 1]>::try_from(positional)
+                .map_err(|_| Failure::Usage("expected only a components file with `--shell`".to_string()))?;
+            if let Some(option) = [(pretty, "--pretty"), (document, "--document")].iter().find(|(set, _)| *set) {
+                return Err(Failure::Usage(format!("`{}` cannot be combined with `--shell`", option.1)));
+            }
+            (components, Target::Shell(shell))
+        }
+        None => {
+            let [components, component] = <[String; 2]>::try_from(positional)
+                .map_err(|_| Failure::Usage("expected a components file and a component name".to_string()))?;
+            (components, Target::Component(component))
+        }
+    };
+    Ok(RenderArgs { components, target, props_file, props, output, document, pretty, sqlite, strict })
+}
+
+fn parse_serve_args(args: &[String]) -> Result<ServeArgs, Failure> {
+    let mut positional = Vec::new();
+    let mut address = DEFAULT_ADDRESS.to_string();
+    let mut shell = None;
+    let mut sqlite = None;
+    let mut strict = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| Failure::Usage(format!("missing value for `{}`", name)))
+        };
+        match arg.as_str() {
+            "--address" => address = value(arg)?,
+            "--shell" => shell = Some(value(arg)?),
+            "--sqlite" => sqlite = Some(value(arg)?),
+            "--strict" => strict = true,
+            arg if arg.starts_with('-') => {
+                return Err(Failure::Usage(format!("unknown option `{}`", arg)));
+            }
+            arg => positional.push(arg.to_string()),
+        }
+    }
+    let [components] = <[String; 1]>::try_from(positional)
+        .map_err(|_| Failure::Usage("expected a components file".to_string()))?;
+    Ok(ServeArgs { components, address, shell, sqlite, strict })
+}
+
+fn load(components: &str, sqlite: Option<&str>, strict: bool) -> Result<Parser, String> {
+    let loaded = if std::path::Path::new(components).is_dir() {
+        Parser::load_dir(components)
+    } else {
+        Parser::load(components)
+    };
+    let mut parser = loaded
+        .map_err(|error| format!("failed to load `{}`: {}", components, error))?;
+    parser.set_strict(strict);
+    if let Some(file) = sqlite {
+        add_sqlite(&mut parser, file)?;
+    }
+    Ok(parser)
+}
+
+#[cfg(feature = "sqlite")]
+fn add_sqlite(parser: &mut Parser, file: &str) -> Result<(), String> {
+    parser.add_sqlite(file).map_err(|error| error.to_string())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn add_sqlite(_: &mut Parser, _: &str) -> Result<(), String> {
+    Err("`--sqlite` needs htymlx to be built with the `sqlite` feature".to_string())
+}
+
+#[cfg(feature = "server")]
+fn serve(args: &ServeArgs) -> Result<(), String> {
+    let parser = load(&args.components, args.sqlite.as_deref(), args.strict)?;
+    let mut server = htymlx::Server::new(parser);
+    if let Some(file) = &args.shell {
+        server.set_shell(read_file(file)?);
+    }
+    eprintln!("Serving `{}` on http://{}", args.components, args.address);
+    server
+        .listen(&args.address)
+        .map_err(|error| error.to_string())
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_: &ServeArgs) -> Result<(), String> {
+    Err("`serve` needs htymlx to be built with the `server` feature".to_string())
+}
+
+fn render(args: &RenderArgs) -> Result<(), String> {
+    let parser = load(&args.components, args.sqlite.as_deref(), args.strict)?;
+    let output = match &args.target {
+        Target::Component(component) => {
+            if !parser.has_component(component) {
+                return Err(format!("component `{}` not found in `{}`", component, args.components));
+            }
+            let props = read_props(args)?;
+            let value = parser
+                .call(component, props)
+                .map_err(|error| format!("failed to render `{}`: {}", component, error))?;
+            let options = if args.pretty { Options::pretty(2) } else { Options::default() };
+            if args.document {
+                document_with(&value, &options)
+            } else {
+                html_with(&value, &options)
+            }
+        }
+        Target::Shell(file) => {
+            let shell = read_file(file)?;
+            let props = read_props(args)?;
+            parser
+                .mount(&shell, props)
+                .map_err(|error| format!("failed to mount into `{}`: {}", file, error))?
+        }
+    };
+    match &args.output {
+        Some(file) => std::fs::write(file, output)
+            .map_err(|error| format!("failed to write `{}`: {}", file, error)),
+        None => {
+            let mut stdout = std::io::stdout();
+            writeln!(stdout, "{}", output)
+                .and_then(|_| stdout.flush())
+                .map_err(|error| format!("failed to write output: {}", error))
+        }
+    }
+}
+
+fn read_props(args: &RenderArgs) -> Result<Value, String> {
+    let mut props = match args.props_file.as_deref() {
+        None => Value::Null,
+        Some(file) => {
+            let input = if file == "-" {
+                let mut input = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut input)
+                    .map_err(|error| format!("failed to read props from stdin: {}", error))?;
+                input
+            } else {
+                read_file(file)?
+            };
+            Yaml::new()
+                .load_str(&input)
+                .map_err(|error| format!("invalid props: {}", error))?
+        }
+    };
+    if args.props.is_empty() {
+        return Ok(props);
+    }
+    if props.is_null() {
+        props = Value::Mapping(IndexMap::new());
+    }
+    let props_map = props
+        .as_mapping_mut()
+        .ok_or_else(|| "props must be a mapping to be combined with --prop".to_string())?;
+    for (key, value) in &args.props {
+        props_map.insert(Value::String(key.clone()), parse_scalar(value));
+    }
+    Ok(props)
+}
+
+fn read_file(file: &str) -> Result<String, String> {
+    std::fs::read_to_string(file).map_err(|error| format!("failed to read `{}`: {}", file, error))
 }
@@ -2,65 +2,309 @@ use std::{collections::HashSet, sync::LazyLock};
 use indexmap::IndexMap;
 use regex::Regex;
 use rust_yaml::Value;
+use crate::error::Error;
 use log::debug;
+use super::expression::{self, Segment};
+use super::filter::Filters;
+use super::function::VerbatimProps;
+use super::constants::HEAD_KEY;
 
+/// A pipe step: a quoted fallback, or the name of a filter with optional arguments. A name without
+/// arguments that no filter has is a fallback too, as `primary` in `$kind|primary`.
+const PIPE: &str = r#"\|(?:('[^']*'|"[^"]*")|([a-zA-Z_][a-zA-Z0-9_]*)(?:\(((?:'[^']*'|"[^"]*"|[^'")])*)\))?)"#;
+
+/// `$name` followed by a path into its value such as `.address.city` or `[0]`, and by pipes
+/// such as `|upper`, `|truncate(20)` or `|'No title'`
 static VAR_RE: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"\$([a-zA-Z_][a-zA-Z0-9_]*)").unwrap()
+    Regex::new(&format!(r"\$([a-zA-Z_][a-zA-Z0-9_]*)((?:\.[a-zA-Z_][a-zA-Z0-9_]*|\[[0-9]+\])*)((?:{})*)", PIPE)).unwrap()
 });
 
-pub fn clear_props(target: &mut Value) {
-    let props: IndexMap<Value, Value> = get_props(target)
-        .iter()
-        .map(|prop| (Value::String(prop.clone()), Value::Null))
-        .collect();
-    apply_props(target, &Value::Mapping(props));
+static PIPE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(PIPE).unwrap());
+
+static PATH_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\.([a-zA-Z_][a-zA-Z0-9_]*)|\[([0-9]+)\]").unwrap()
+});
+
+/// Replaces every prop nobody supplied with `null`.
+/// Strings left empty afterwards become `null` too, so they are omitted from attributes.
+pub fn clear_props(target: &mut Value, filters: &Filters, verbatim: &VerbatimProps) -> Result<(), Error> {
+    match target {
+        Value::String(_) => {
+            let props: IndexMap<Value, Value> = get_props(target, verbatim)
+                .iter()
+                .map(|prop| (Value::String(prop.clone()), Value::Null))
+                .collect();
+            if props.is_empty() {
+                return Ok(());
+            }
+            apply_props(target, &Value::Mapping(props), filters, verbatim)?;
+            if target.as_str() == Some("") {
+                *target = Value::Null;
+            }
+            Ok(())
+        }
+        Value::Sequence(values) => values.iter_mut().try_for_each(|value| clear_props(value, filters, verbatim)),
+        Value::Mapping(index_map) => {
+            let kept = verbatim_keys(index_map, verbatim);
+            index_map
+                .iter_mut()
+                .filter(|(key, _)| !kept.contains(key))
+                .try_for_each(|(_, value)| clear_props(value, filters, verbatim))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Keys of `map` kept as is, e.g. the `query` of `from: sql` whose `$props` are bound as
+/// parameters by the function
+pub fn verbatim_keys<'v>(map: &IndexMap<Value, Value>, verbatim: &'v VerbatimProps) -> &'v [Value] {
+    map.get(&Value::String("from".into()))
+        .and_then(Value::as_str)
+        .and_then(|from| verbatim.get(from))
+        .map_or(&[], Vec::as_slice)
+}
+
+/// Props bound by the loop of `map`, the one of each element first
+pub fn loop_names(map: &IndexMap<Value, Value>) -> Result<[String; 5], Error> {
+    let name = match map.get(&Value::String("as".into())) {
+        None | Some(Value::Null) => "item",
+        Some(Value::String(name)) => name.trim_start_matches('$'),
+        Some(value) => return Err(Error::syntax(format!("`as` expects a prop name, got `{}`", value))),
+    };
+    Ok([name, "index", "first", "last", "key"].map(String::from))
+}
+
+/// Props bound by the loop of `map` if it has one, they are hidden from everything but its `each`.
+/// `key` is only bound when iterating a mapping, so it stays visible unless `each` is one.
+fn hidden_names(map: &IndexMap<Value, Value>) -> Result<Vec<String>, Error> {
+    let Some(items) = map.get(&Value::String("each".into())) else {
+        return Ok(vec![]);
+    };
+    let mut names = Vec::from(loop_names(map)?);
+    if !items.is_mapping() {
+        names.pop();
+    }
+    Ok(names)
 }
 
-pub fn get_props(comp: &Value) -> Vec<String> {
+pub fn get_props(comp: &Value, verbatim: &VerbatimProps) -> Vec<String> {
+    collect_props(comp, verbatim, None)
+}
+
+/// Props that have no fallback, the ones missing when they are left unbound
+pub fn get_required_props(comp: &Value, verbatim: &VerbatimProps, filters: &Filters) -> Vec<String> {
+    collect_props(comp, verbatim, Some(filters))
+}
+
+/// All the props of `comp`, or only the `required` ones, given the filters telling fallbacks apart
+fn collect_props(comp: &Value, verbatim: &VerbatimProps, required: Option<&Filters>) -> Vec<String> {
     match comp {
-        Value::String(s) => VAR_RE
-            .captures_iter(s)
-            .map(|mat| mat.get(1).unwrap().as_str().to_string())
-            .collect(),
-        Value::Sequence(values) => values.iter().flat_map(get_props).collect(),
-        Value::Mapping(index_map) => index_map.values().flat_map(get_props).collect(),
+        Value::String(s) => get_string_props(s, required),
+        Value::Sequence(values) => values.iter().flat_map(|value| collect_props(value, verbatim, required)).collect(),
+        Value::Mapping(index_map) => {
+            let kept = verbatim_keys(index_map, verbatim);
+            let hidden = hidden_names(index_map).unwrap_or_default();
+            index_map
+                .iter()
+                .filter(|(key, _)| !kept.contains(key))
+                .flat_map(|(key, value)| {
+                    let mut props = collect_props(value, verbatim, required);
+                    if key.as_str() != Some("each") {
+                        props.retain(|prop| !hidden.contains(prop));
+                    }
+                    props
+                })
+                .collect()
+        }
         _ => vec![],
     }
 }
 
-pub fn apply_props(target: &mut Value, source: &Value) {
+fn get_string_props(text: &str, required: Option<&Filters>) -> Vec<String> {
+    let Ok(segments) = expression::split_segments(text) else {
+        return get_text_props(text, required);
+    };
+    segments
+        .into_iter()
+        .flat_map(|segment| match segment {
+            Segment::Text(text) => get_text_props(text, required),
+            Segment::Expression(source) => expression::parse(source)
+                .map(|expression| if required.is_some() { expression.required_variables() } else { expression.variables() })
+                .unwrap_or_else(|_| get_text_props(source, required)),
+        })
+        .collect()
+}
+
+fn get_text_props(text: &str, required: Option<&Filters>) -> Vec<String> {
+    let mut props = vec![];
+    for caps in VAR_RE.captures_iter(text) {
+        let mut has_fallback = false;
+        let mut arguments = vec![];
+        for step in PIPE_RE.captures_iter(&caps[3]) {
+            has_fallback |= step.get(1).is_some()
+                || step.get(2).is_some_and(|name| {
+                    name.as_str() == "default"
+                        || step.get(3).is_none() && required.is_some_and(|filters| !filters.contains_key(name.as_str()))
+                });
+            if let Some(source) = step.get(3)
+                && let Ok(expressions) = expression::parse_arguments(source.as_str()) {
+                arguments.extend(expressions.iter().flat_map(|expression| {
+                    if required.is_some() { expression.required_variables() } else { expression.variables() }
+                }));
+            }
+        }
+        if required.is_none() || !has_fallback {
+            props.push(caps[1].to_string());
+        }
+        props.extend(arguments);
+    }
+    props
+}
+
+/// Evaluates `${...}` once every variable it uses is present in `source_map`.
+/// Returns `None` while some variable is still unbound so a later call can finish it.
+fn eval_expression(source: &str, source_map: &IndexMap<Value, Value>, filters: &Filters) -> Result<Option<Value>, Error> {
+    let expression = expression::parse(source)
+        .map_err(|message| Error::expression(source, message))?;
+    eval_expression_of(&expression, source, source_map, filters)
+}
+
+fn eval_expression_of(
+    expression: &expression::Expression,
+    source: &str,
+    source_map: &IndexMap<Value, Value>,
+    filters: &Filters,
+) -> Result<Option<Value>, Error> {
+    let is_bound = expression
+        .variables()
+        .iter()
+        .all(|name| source_map.contains_key(&Value::String(name.clone())));
+    if !is_bound {
+        return Ok(None);
+    }
+    expression
+        .eval(source_map, filters)
+        .map(Some)
+        .map_err(|message| Error::expression(source, message))
+}
+
+/// Value of a prop reference captured by `VAR_RE` once its pipes ran, `None` while the prop or
+/// an argument of its filters is unbound. Fallbacks replace a `null` value.
+///
+/// Along with it comes the length of the reference: a path only goes into mappings and sequences,
+/// so in `$id.png` with a number `id` the reference is `$id` and `.png` stays text, pipes included.
+/// The same goes for a missing `id`, unless pipes follow the path as in `$user.name|'Guest'`.
+fn resolve_var(caps: &regex::Captures, source_map: &IndexMap<Value, Value>, filters: &Filters) -> Result<Option<(Value, usize)>, Error> {
+    let Some(value) = source_map.get(&Value::String(caps[1].to_string())) else {
+        return Ok(None);
+    };
+    let mut value = value.clone();
+    let start = caps.get(0).unwrap().start();
+    let path = caps.get(2).unwrap();
+    let has_pipes = !caps[3].is_empty();
+    for (index, step) in PATH_RE.captures_iter(path.as_str()).enumerate() {
+        let walks = match value {
+            Value::Mapping(_) | Value::Sequence(_) => true,
+            // A missing entry inside the path is `null` too
+            Value::Null => index > 0 || has_pipes,
+            _ => false,
+        };
+        if !walks {
+            return Ok(Some((value, path.start() + step.get(0).unwrap().start() - start)));
+        }
+        let key = match (step.get(1), step.get(2)) {
+            (Some(name), _) => Value::String(name.as_str().into()),
+            (_, Some(index)) => index.as_str().parse().map(Value::Int).unwrap_or(Value::Null),
+            _ => Value::Null,
+        };
+        value = expression::member(&value, &key);
+    }
+    for step in PIPE_RE.captures_iter(&caps[3]) {
+        if let Some(quoted) = step.get(1) {
+            if value.is_null() {
+                let quoted = quoted.as_str();
+                value = Value::String(quoted[1..quoted.len() - 1].to_string());
+            }
+            continue;
+        }
+        let name = &step[2];
+        let Some(filter) = filters.get(name) else {
+            if step.get(3).is_some() {
+                return Err(Error::expression(&caps[0], format!("Unknown filter `{}`", name)));
+            }
+            if value.is_null() {
+                value = Value::String(name.into());
+            }
+            continue;
+        };
+        let mut arguments = vec![];
+        if let Some(source) = step.get(3) {
+            let expressions = expression::parse_arguments(source.as_str())
+                .map_err(|message| Error::expression(&caps[0], message))?;
+            for expression in expressions {
+                let Some(argument) = eval_expression_of(&expression, &caps[0], source_map, filters)? else {
+                    return Ok(None);
+                };
+                arguments.push(argument);
+            }
+        }
+        value = filter(value, &arguments).map_err(|message| Error::function(name, message))?;
+    }
+    Ok(Some((value, caps[0].len())))
+}
+
+pub fn apply_props(target: &mut Value, source: &Value, filters: &Filters, verbatim: &VerbatimProps) -> Result<(), Error> {
     let source_map = source.as_mapping().expect("Source should always be mapping!");
     // debug!("apply_props source_map {:?}", source_map);
     match target {
         Value::String(target_str) => {
             // debug!("apply_props string {}", target_str);
-            let get_prop_name = |prop: &str| String::from(&prop[1..]);
-            let has_prop_to_apply = source_map.keys().any(|k| {
-                match k {
-                    Value::String(s) => *s == get_prop_name(target_str),
-                    _ => true,
-                }
-            });
-            let prop_value = source_map.get(&Value::String(get_prop_name(target_str)));
-            if has_prop_to_apply && let Some(replacement) = prop_value {
-                *target = replacement.clone();
-                return;
+            if let Some(caps) = VAR_RE.captures(target_str)
+                && caps[0].len() == target_str.len()
+                && let Some((replacement, length)) = resolve_var(&caps, source_map, filters)?
+                && length == target_str.len() {
+                *target = replacement;
+                return Ok(());
             }
-            let result = VAR_RE.replace_all(target_str, |caps: &regex::Captures| {
-                let var_name = &caps[1];
-                // debug!("replace with re {}", var_name);
-                if let Some(replacement) = source_map.get(&Value::String(var_name.to_string())) {
-                    match replacement {
-                        Value::Null => "".to_string(),
-                        Value::String(s) => s.to_string(),
-                        v => v.to_string(),
+            if let Some(source) = expression::whole_expression(target_str)
+                && let Some(value) = eval_expression(source, source_map, filters)? {
+                *target = value;
+                return Ok(());
+            }
+            let segments = expression::split_segments(target_str)
+                .map_err(|message| Error::expression(target_str, message))?;
+            let mut result = String::with_capacity(target_str.len());
+            for segment in segments {
+                match segment {
+                    Segment::Text(text) => {
+                        let mut position = 0;
+                        for caps in VAR_RE.captures_iter(text) {
+                            let whole = caps.get(0).unwrap();
+                            result.push_str(&text[position..whole.start()]);
+                            match resolve_var(&caps, source_map, filters)? {
+                                Some((replacement, length)) => {
+                                    result.push_str(&expression::to_text(&replacement));
+                                    result.push_str(&whole.as_str()[length..]);
+                                }
+                                None => result.push_str(whole.as_str()),
+                            }
+                            position = whole.end();
+                        }
+                        result.push_str(&text[position..]);
                     }
-                } else {
-                    caps[0].to_string()
+                    Segment::Expression(source) => match eval_expression(source, source_map, filters)? {
+                        Some(value) => result.push_str(&expression::to_text(&value)),
+                        None => {
+                            result.push_str("${");
+                            result.push_str(source);
+                            result.push('}');
+                        }
+                    },
                 }
-            });
+            }
             // debug!("result: {}", result);
-            *target = Value::String(result.trim().to_string())
+            *target = Value::String(result.trim().to_string());
+            Ok(())
         }
         target => {
             if matches!(target, Value::Sequence(_)) {
@@ -68,15 +312,32 @@ pub fn apply_props(target: &mut Value, source: &Value) {
                     .as_sequence_mut()
                     .unwrap()
                     .iter_mut()
-                    .for_each(|item| apply_props(item, source));
+                    .try_for_each(|item| apply_props(item, source, filters, verbatim))?;
             }
-            if matches!(target, Value::Mapping(_)) {
-                target
-                    .as_mapping_mut()
-                    .unwrap()
-                    .values_mut()
-                    .for_each(|value| apply_props(value, source));
+            if let Value::Mapping(target_map) = target {
+                let kept = verbatim_keys(target_map, verbatim);
+                // A loop binds its props to its body itself, once for each element, which ones
+                // depends on what `each` iterates
+                let each = Value::String("each".into());
+                if !kept.contains(&each) && let Some(items) = target_map.get_mut(&each) {
+                    apply_props(items, source, filters, verbatim)?;
+                }
+                let hidden = hidden_names(target_map)?;
+                let visible = Value::Mapping(
+                    source_map
+                        .iter()
+                        .filter(|(key, _)| !key.as_str().is_some_and(|key| hidden.iter().any(|name| name == key)))
+                        .map(|(key, value)| (key.clone(), value.clone()))
+                        .collect(),
+                );
+                target_map
+                    .iter_mut()
+                    .filter(|(key, _)| !kept.contains(key) && **key != each)
+                    .try_for_each(|(_, value)| {
+                        apply_props(value, if hidden.is_empty() { source } else { &visible }, filters, verbatim)
+                    })?;
             }
+            Ok(())
         }
     }
 }
@@ -88,7 +349,19 @@ pub fn apply_merge(target: &mut Value, source: &Value) {
             let target_map = target.as_mapping().unwrap();
             let mut merged = target_map.clone();
             for (key, source_value) in source_map.iter() {
-                if let Some(target_value) = merged.get(key) {
+                if key.as_str() == Some(HEAD_KEY)
+                    && let Some(target_value) = merged.get(key) {
+                    // Head entries of the caller are added to the ones of the component
+                    let entries = [target_value, source_value]
+                        .into_iter()
+                        .flat_map(|value| match value {
+                            Value::Null => vec![],
+                            Value::Sequence(values) => values.clone(),
+                            value => vec![value.clone()],
+                        })
+                        .collect();
+                    merged.insert(key.clone(), Value::Sequence(entries));
+                } else if let Some(target_value) = merged.get(key) {
                     let mut target_value = target_value.clone();
                     apply_merge(&mut target_value, source_value);
                     merged.insert(key.clone(), target_value);
@@ -105,29 +378,29 @@ pub fn apply_merge(target: &mut Value, source: &Value) {
     }
 }
 
-pub fn apply(target: &mut Value, source: &mut Value) {
+pub fn apply(target: &mut Value, source: &mut Value, filters: &Filters, verbatim: &VerbatimProps) -> Result<(), Error> {
     debug!("Apply to {}  with  {}", target, source);
     if *source == Value::Null {
-        return;
+        return Ok(());
     }
     if let Value::Sequence(target_seq) = target
         && let Value::Sequence(source_seq) = source {
         target_seq.append(source_seq);
-        return;
+        return Ok(());
     }
     if let Value::Sequence(source_seq) = source {
         let new_target_seq: Vec<Value> = source_seq
             .drain(..)
             .map(|mut source_item| {
                 let mut model = target.clone();
-                apply(&mut model, &mut source_item);
-                model
+                apply(&mut model, &mut source_item, filters, verbatim)?;
+                Ok(model)
             })
-            .collect();
+            .collect::<Result<Vec<Value>, Error>>()?;
             *target = Value::Sequence(new_target_seq);
-        return;
+        return Ok(());
     }
-    let target_props: HashSet<String> = get_props(target).into_iter().collect();
+    let target_props: HashSet<String> = get_props(target, verbatim).into_iter().collect();
     let source_props: HashSet<String> = match source {
         Value::Mapping(map) => map.keys().filter_map(|k| {
             if let Value::String(s) = k {
@@ -142,7 +415,7 @@ pub fn apply(target: &mut Value, source: &mut Value) {
     debug!("Comon props {:?}", common_props);
     if common_props.count() > 0 {
         debug!("Before apply props {} {}", target, source);
-        apply_props(target, source);
+        apply_props(target, source, filters, verbatim)?;
         // Remove applied props from source
         debug!("Before retain {} {}", target, source);
         source
@@ -156,11 +429,13 @@ pub fn apply(target: &mut Value, source: &mut Value) {
     } else {
         apply_merge(target, source);
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::filter::builtins;
     use rust_yaml::Yaml;
     #[test]
     fn test_get_props() {
@@ -174,7 +449,7 @@ key3:
   subkey2: "also $var5"
 "#;
         let value = Yaml::new().load_str(yaml_str).unwrap();
-        let props = get_props(&value);
+        let props = get_props(&value, &VerbatimProps::new());
         let mut expected = vec!["var1".to_string(), "var2".to_string(), "var3".to_string(), "var4".to_string(), "var5".to_string()];
         expected.sort();
         let mut props_sorted = props;
@@ -201,7 +476,7 @@ setting1: "Enabled"
 "#;
         let mut target = Yaml::new().load_str(target_yaml).unwrap();
         let mut source = Yaml::new().load_str(source_yaml).unwrap();
-        apply(&mut target, &mut source);
+        apply(&mut target, &mut source, &builtins(), &VerbatimProps::new()).unwrap();
         let expected_yaml = r#"
 message: "Hello, Alice!"
 items:
@@ -227,7 +502,7 @@ user:
 "#;
         let mut target = Yaml::new().load_str(target_yaml).unwrap();
         let mut source = Yaml::new().load_str(source_yaml).unwrap();
-        apply(&mut target, &mut source);
+        apply(&mut target, &mut source, &builtins(), &VerbatimProps::new()).unwrap();
         let expected_yaml = r#"greeting:
     first: "John"
     last: "Doe"
@@ -236,6 +511,38 @@ user:
         assert_eq!(target, expected);
     }
 
+    #[test]
+    fn test_apply_props_nested() {
+        let target_yaml = r#"
+name: $user.name
+city: $user.address.city
+first: $user.roles[0]
+missing: $user.roles[3]
+text: "${$user.roles[1]}: $user.name <$user.email>. Bye $user.name."
+"#;
+        let source_yaml = r#"
+user:
+    name: "John"
+    email: "john@example.com"
+    address:
+        city: "Lisbon"
+    roles: [admin, editor]
+"#;
+        let mut target = Yaml::new().load_str(target_yaml).unwrap();
+        let mut source = Yaml::new().load_str(source_yaml).unwrap();
+        assert_eq!(get_props(&target, &VerbatimProps::new()), vec!["user"; 8]);
+        apply(&mut target, &mut source, &builtins(), &VerbatimProps::new()).unwrap();
+        let expected_yaml = r#"
+name: "John"
+city: "Lisbon"
+first: "admin"
+missing: null
+text: "editor: John <john@example.com>. Bye John."
+"#;
+        let expected = Yaml::new().load_str(expected_yaml).unwrap();
+        assert_eq!(target, expected);
+    }
+
     #[test]
     fn test_apply_props_with_vec() {
         let target_yaml = r#"
@@ -247,7 +554,7 @@ list:
 age: "30""#;
         let mut target = Yaml::new().load_str(target_yaml).unwrap();
         let mut source = Yaml::new().load_str(source_yaml).unwrap();
-        apply(&mut target, &mut source);
+        apply(&mut target, &mut source, &builtins(), &VerbatimProps::new()).unwrap();
         let expected_yaml = r#"list:
   - "Hello, Bob!"
   - "Your age is 30."
@@ -266,7 +573,7 @@ list: $items
   - Item2"#;
         let mut target = Yaml::new().load_str(target_yaml).unwrap();
         let mut source = Yaml::new().load_str(source_yaml).unwrap();
-        apply(&mut target, &mut source);
+        apply(&mut target, &mut source, &builtins(), &VerbatimProps::new()).unwrap();
         let expected_yaml = r#"list:
   - Item1
   - Item2
@@ -287,7 +594,7 @@ list:
   - Item2"#;
         let mut target = Yaml::new().load_str(target_yaml).unwrap();
         let mut source = Yaml::new().load_str(source_yaml).unwrap();
-        apply(&mut target, &mut source);
+        apply(&mut target, &mut source, &builtins(), &VerbatimProps::new()).unwrap();
         let expected_yaml = r#"list: [[Item1, Item2], [Item1, Item2]]"#;
         let expected = Yaml::new().load_str(expected_yaml).unwrap();
         assert_eq!(target, expected);
@@ -307,7 +614,7 @@ config:
 "#;
         let mut target = Yaml::new().load_str(target_yaml).unwrap();
         let mut source = Yaml::new().load_str(source_yaml).unwrap();
-        apply(&mut target, &mut source);
+        apply(&mut target, &mut source, &builtins(), &VerbatimProps::new()).unwrap();
         let expected_yaml = r#"
 config:
   setting1: "Value1"
@@ -1,3 +1,6 @@
+/// Default number of nested calls a component can make to itself
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
 pub static IMPLICIT_HTML_COMPONENTS: [&str; 31] = [
     "div",
     "span",
@@ -30,4 +33,114 @@ pub static IMPLICIT_HTML_COMPONENTS: [&str; 31] = [
     "tr",
     "td",
     "th",
-];
\ No newline at end of file
+];
+
+/// Key of a component declaring the elements it needs in the document `<head>`
+pub const HEAD_KEY: &str = "head";
+
+/// Key of the content a caller gives to the named slots of a component
+pub const SLOTS_KEY: &str = "slots";
+
+/// Prop holding the slots inside a component, e.g. `$slot.header`
+pub const SLOT_PROP: &str = "slot";
+
+/// Key of the values a component makes available to the components it calls
+pub const PROVIDE_KEY: &str = "provide";
+
+/// Prop holding the provided values, e.g. `$ctx.theme`
+pub const CONTEXT_PROP: &str = "ctx";
+
+/// Keys and props with a meaning of their own, never taken from outside callers such as requests
+pub static RESERVED_PROPS: [&str; 11] = [
+    "from",
+    "if",
+    "else",
+    "each",
+    "as",
+    "props",
+    HEAD_KEY,
+    SLOTS_KEY,
+    SLOT_PROP,
+    PROVIDE_KEY,
+    CONTEXT_PROP,
+];
+
+/// Elements that can be written as `title: Text` in `head:` entries
+pub static HEAD_HTML_ELEMENTS: [&str; 7] = ["title", "meta", "link", "script", "style", "base", "noscript"];
+
+/// Elements kept on the line of the surrounding text when pretty printing
+pub static INLINE_HTML_ELEMENTS: [&str; 30] = [
+    "a",
+    "abbr",
+    "b",
+    "bdi",
+    "bdo",
+    "br",
+    "button",
+    "cite",
+    "code",
+    "data",
+    "dfn",
+    "em",
+    "i",
+    "img",
+    "input",
+    "kbd",
+    "label",
+    "mark",
+    "q",
+    "s",
+    "samp",
+    "select",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "time",
+    "u",
+    "var",
+];
+
+/// Elements whose whitespace is content, pretty printing leaves them untouched
+pub static PREFORMATTED_HTML_ELEMENTS: [&str; 4] = ["pre", "textarea", "script", "style"];
+
+/// Elements whose text is not escaped, it only ends at their closing tag
+pub static RAW_TEXT_HTML_ELEMENTS: [&str; 2] = ["script", "style"];
+
+/// Elements whose content is SVG or MathML rather than HTML
+pub static FOREIGN_HTML_ELEMENTS: [&str; 2] = ["svg", "math"];
+
+/// Elements that never have children nor a closing tag
+pub static VOID_HTML_ELEMENTS: [&str; 14] = [
+    "area",
+    "base",
+    "br",
+    "col",
+    "embed",
+    "hr",
+    "img",
+    "input",
+    "link",
+    "meta",
+    "param",
+    "source",
+    "track",
+    "wbr",
+];
+
+
+/// Every standard HTML element, `from` values outside this list must be components in strict mode
+pub static HTML_TAGS: [&str; 115] = [
+    "a", "abbr", "address", "area", "article", "aside", "audio", "b", "base", "bdi", "bdo",
+    "blockquote", "body", "br", "button", "canvas", "caption", "cite", "code", "col", "colgroup",
+    "data", "datalist", "dd", "del", "details", "dfn", "dialog", "div", "dl", "dt", "em", "embed",
+    "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6",
+    "head", "header", "hgroup", "hr", "html", "i", "iframe", "img", "input", "ins", "kbd", "label",
+    "legend", "li", "link", "main", "map", "mark", "menu", "meta", "meter", "nav", "noscript",
+    "object", "ol", "optgroup", "option", "output", "p", "param", "picture", "pre", "progress",
+    "q", "rp", "rt", "ruby", "s", "samp", "script", "search", "section", "select", "slot", "small",
+    "source", "span", "strong", "style", "sub", "summary", "sup", "table", "tbody", "td",
+    "template", "textarea", "tfoot", "th", "thead", "time", "title", "tr", "track", "u", "ul",
+    "var", "video", "wbr", "svg", "math",
+];
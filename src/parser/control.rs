@@ -0,0 +1,170 @@
+use indexmap::IndexMap;
+use rust_yaml::Value;
+use super::apply::{apply_props, clear_props, get_props, loop_names};
+use super::filter::Filters;
+use super::function::{Function, VerbatimProps};
+use super::utils::is_truthy;
+use crate::error::Error;
+
+/// Resolves the loops and the conditions of `value` and of its children.
+///
+/// Conditions are the `if` and `else` keys:
+///
+/// ```yaml
+/// badge:
+///     from: span
+///     if: $count
+///     else: No messages
+///     body: $count messages
+/// ```
+///
+/// A node whose condition is falsy is replaced by its `else`, or removed without one: it is dropped
+/// from sequences and becomes `null` elsewhere. Conditions using props that are still unbound are
+/// left for a later call, unless `is_final` where those props are `null`. The `body` of a call to
+/// one of the `functions` is left to the function, its props are bound when it renders it.
+///
+/// Loops are the `each` and `as` keys, see [`expand_loop`].
+pub fn resolve_control_flow(
+    value: &mut Value,
+    is_final: bool,
+    filters: &Filters,
+    verbatim: &VerbatimProps,
+    functions: &IndexMap<String, Function>,
+) -> Result<(), Error> {
+    if let Value::Sequence(values) = value {
+        let mut kept = Vec::with_capacity(values.len());
+        for mut item in values.drain(..) {
+            if resolve_node(&mut item, is_final, filters, verbatim, functions)? {
+                kept.push(item);
+            }
+        }
+        *values = kept;
+    } else if value.is_mapping() && !resolve_node(value, is_final, filters, verbatim, functions)? {
+        *value = Value::Null;
+    }
+    Ok(())
+}
+
+/// Resolves the loop and condition of `value` and the ones of its children, `false` when it has to be removed
+fn resolve_node(
+    value: &mut Value,
+    is_final: bool,
+    filters: &Filters,
+    verbatim: &VerbatimProps,
+    functions: &IndexMap<String, Function>,
+) -> Result<bool, Error> {
+    let key_if = Value::String("if".into());
+    let key_else = Value::String("else".into());
+    expand_loop(value, is_final, filters, verbatim)?;
+    if let Value::Mapping(map) = value
+        && let Some(condition) = map.get(&key_if) {
+        let is_met = if is_final {
+            let mut condition = condition.clone();
+            clear_props(&mut condition, filters, verbatim)?;
+            Some(is_truthy(&condition))
+        } else if get_props(condition, verbatim).is_empty() {
+            Some(is_truthy(condition))
+        } else {
+            None
+        };
+        match is_met {
+            Some(true) => {
+                map.shift_remove(&key_if);
+                map.shift_remove(&key_else);
+            }
+            Some(false) => {
+                let Some(otherwise) = map.shift_remove(&key_else) else {
+                    return Ok(false);
+                };
+                *value = otherwise;
+                return resolve_node(value, is_final, filters, verbatim, functions);
+            }
+            None => {}
+        }
+    }
+    match value {
+        Value::Mapping(map) => {
+            let is_function_call = map
+                .get(&Value::String("from".into()))
+                .and_then(Value::as_str)
+                .is_some_and(|from| functions.contains_key(from));
+            for (key, value) in map.iter_mut() {
+                let is_function_body = is_function_call && key.as_str() == Some("body");
+                if *key != key_if && *key != key_else && !is_function_body {
+                    resolve_control_flow(value, is_final, filters, verbatim, functions)?;
+                }
+            }
+        }
+        Value::Sequence(_) => resolve_control_flow(value, is_final, filters, verbatim, functions)?,
+        _ => {}
+    }
+    Ok(true)
+}
+
+/// Repeats the body of a mapping with `each` for every element of its value.
+///
+/// ```yaml
+/// list:
+///     from: ul
+///     each: $items
+///     as: item
+///     body:
+///         li: $index. $item
+/// ```
+///
+/// The body is expanded with `$item` (or the name given by `as`), `$index` counting from 0,
+/// `$first` and `$last`, plus `$key` when iterating a mapping. The other keys are the parent
+/// element; without `body` the whole mapping is repeated instead. Loops over props that are
+/// still unbound are left for a later call, unless `is_final` where they repeat nothing.
+fn expand_loop(value: &mut Value, is_final: bool, filters: &Filters, verbatim: &VerbatimProps) -> Result<(), Error> {
+    let Value::Mapping(map) = value else {
+        return Ok(());
+    };
+    let Some(items) = map.get(&Value::String("each".into())) else {
+        return Ok(());
+    };
+    let items = if is_final {
+        let mut items = items.clone();
+        clear_props(&mut items, filters, verbatim)?;
+        items
+    } else if get_props(items, verbatim).is_empty() {
+        items.clone()
+    } else {
+        return Ok(());
+    };
+    let names = loop_names(map)?;
+    map.shift_remove(&Value::String("each".into()));
+    map.shift_remove(&Value::String("as".into()));
+    let entries: Vec<(Option<Value>, Value)> = match items {
+        Value::Sequence(values) => values.into_iter().map(|value| (None, value)).collect(),
+        Value::Mapping(values) => values.into_iter().map(|(key, value)| (Some(key), value)).collect(),
+        Value::Null => vec![],
+        value => return Err(Error::syntax(format!("`each` expects a list or a mapping, got `{}`", value))),
+    };
+    let body = map.shift_remove(&Value::String("body".into()));
+    let template = body.clone().unwrap_or_else(|| Value::Mapping(map.clone()));
+    let count = entries.len();
+    let mut result = Vec::with_capacity(count);
+    for (index, (key, item)) in entries.into_iter().enumerate() {
+        let mut bindings = IndexMap::new();
+        bindings.insert(Value::String(names[0].clone()), item);
+        bindings.insert(Value::String("index".into()), Value::Int(index as i64));
+        bindings.insert(Value::String("first".into()), Value::Bool(index == 0));
+        bindings.insert(Value::String("last".into()), Value::Bool(index + 1 == count));
+        if let Some(key) = key {
+            bindings.insert(Value::String("key".into()), key);
+        }
+        let mut copy = template.clone();
+        apply_props(&mut copy, &Value::Mapping(bindings), filters, verbatim)?;
+        match copy {
+            Value::Sequence(values) => result.extend(values),
+            copy => result.push(copy),
+        }
+    }
+    if body.is_some() {
+        map.insert(Value::String("body".into()), Value::Sequence(result));
+    } else {
+        *value = Value::Sequence(result);
+    }
+    Ok(())
+}
@@ -0,0 +1,693 @@
+use std::cmp::Ordering;
+use indexmap::IndexMap;
+use rust_yaml::Value;
+use super::filter::Filters;
+use super::utils::is_truthy;
+
+/// Parsed form of the text between `${` and `}`
+///
+/// ```yaml
+/// box:
+///     body: "${$count + 1}"
+/// ```
+///
+/// Variables can be written as `$count` or just `count`, and their content reached with
+/// `$user.name` or `$items[0]`. Values go through [`Filters`] with `items|join(', ')`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(Value),
+    Variable(String),
+    /// `value.name` or `value[key]`
+    Member(Box<Expression>, Box<Expression>),
+    /// `value|filter` or `value|filter(arguments)`
+    Pipe(Box<Expression>, String, Vec<Expression>),
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Not,
+    Negate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    /// `??`, the right value when the left one is `null`
+    Coalesce,
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+/// Piece of a string containing `${...}` expressions
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    /// Source of the expression, without the surrounding `${` and `}`
+    Expression(&'a str),
+}
+
+/// Splits `text` into plain text and `${...}` segments.
+///
+/// Braces inside quoted strings do not close the expression.
+pub fn split_segments(text: &str) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+        }
+        let source = &rest[start + 2..];
+        let end = find_expression_end(source)
+            .ok_or_else(|| format!("Unterminated expression in `{}`", text))?;
+        segments.push(Segment::Expression(&source[..end]));
+        rest = &source[end + 1..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    Ok(segments)
+}
+
+fn find_expression_end(source: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut depth = 0;
+    for (index, char) in source.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if char == '\\' => escaped = true,
+            Some(open) if char == open => quote = None,
+            Some(_) => {}
+            None => match char {
+                '\'' | '"' => quote = Some(char),
+                '{' => depth += 1,
+                '}' if depth == 0 => return Some(index),
+                '}' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+/// Returns the expression source when `text` is exactly one `${...}` segment
+pub fn whole_expression(text: &str) -> Option<&str> {
+    match split_segments(text.trim()).ok()?.as_slice() {
+        [Segment::Expression(source)] => Some(source),
+        _ => None,
+    }
+}
+
+/// Parses `arguments` written as `(1, 'a')` after a filter name, without the parentheses
+pub fn parse_arguments(source: &str) -> Result<Vec<Expression>, String> {
+    let mut tokens = tokenize(source)?;
+    tokens.push(Token::CloseParen);
+    let mut parser = ExpressionParser { tokens, position: 0 };
+    let arguments = parser.parse_arguments()?;
+    match parser.peek() {
+        None => Ok(arguments),
+        Some(token) => Err(format!("Unexpected {:?} in `{}`", token, source)),
+    }
+}
+
+pub fn parse(source: &str) -> Result<Expression, String> {
+    let tokens = tokenize(source)?;
+    let mut parser = ExpressionParser { tokens, position: 0 };
+    let expression = parser.parse_coalesce()?;
+    match parser.peek() {
+        None => Ok(expression),
+        Some(token) => Err(format!("Unexpected {:?} in `{}`", token, source)),
+    }
+}
+
+impl Expression {
+    /// Names of all variables referenced by the expression, in order of appearance
+    pub fn variables(&self) -> Vec<String> {
+        match self {
+            Expression::Literal(_) => vec![],
+            Expression::Variable(name) => vec![name.clone()],
+            Expression::Unary(_, operand) => operand.variables(),
+            Expression::Member(left, right) | Expression::Binary(_, left, right) => {
+                let mut variables = left.variables();
+                variables.extend(right.variables());
+                variables
+            }
+            Expression::Pipe(value, _, arguments) => {
+                let mut variables = value.variables();
+                variables.extend(arguments.iter().flat_map(Expression::variables));
+                variables
+            }
+        }
+    }
+
+    /// Variables without a fallback, the ones not on the left of a `??` or piped to `default`
+    pub fn required_variables(&self) -> Vec<String> {
+        match self {
+            Expression::Binary(BinaryOperator::Coalesce, _, right) => right.required_variables(),
+            Expression::Pipe(value, name, arguments) => {
+                let mut variables = if name == "default" { vec![] } else { value.required_variables() };
+                variables.extend(arguments.iter().flat_map(Expression::required_variables));
+                variables
+            }
+            Expression::Literal(_) => vec![],
+            Expression::Variable(name) => vec![name.clone()],
+            Expression::Unary(_, operand) => operand.required_variables(),
+            Expression::Member(left, right) | Expression::Binary(_, left, right) => {
+                let mut variables = left.required_variables();
+                variables.extend(right.required_variables());
+                variables
+            }
+        }
+    }
+
+    pub fn eval(&self, props: &IndexMap<Value, Value>, filters: &Filters) -> Result<Value, String> {
+        match self {
+            Expression::Literal(value) => Ok(value.clone()),
+            Expression::Variable(name) => props
+                .get(&Value::String(name.clone()))
+                .cloned()
+                .ok_or_else(|| format!("Unbound variable `{}`", name)),
+            Expression::Member(value, key) => Ok(member(&value.eval(props, filters)?, &key.eval(props, filters)?)),
+            Expression::Pipe(value, name, arguments) => {
+                let filter = filters.get(name).ok_or_else(|| format!("Unknown filter `{}`", name))?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| argument.eval(props, filters))
+                    .collect::<Result<Vec<Value>, String>>()?;
+                filter(value.eval(props, filters)?, &arguments).map_err(|message| format!("`{}`: {}", name, message))
+            }
+            Expression::Unary(UnaryOperator::Not, operand) => {
+                Ok(Value::Bool(!is_truthy(&operand.eval(props, filters)?)))
+            }
+            Expression::Unary(UnaryOperator::Negate, operand) => {
+                match number(&operand.eval(props, filters)?, "-")? {
+                    Number::Int(value) => value
+                        .checked_neg()
+                        .map(Value::Int)
+                        .ok_or_else(|| format!("Integer overflow in `-{}`", value)),
+                    Number::Float(value) => Ok(Value::Float(-value)),
+                }
+            }
+            Expression::Binary(BinaryOperator::Coalesce, left, right) => {
+                let left = left.eval(props, filters)?;
+                if left.is_null() { right.eval(props, filters) } else { Ok(left) }
+            }
+            Expression::Binary(BinaryOperator::Or, left, right) => {
+                let left = left.eval(props, filters)?;
+                if is_truthy(&left) { Ok(left) } else { right.eval(props, filters) }
+            }
+            Expression::Binary(BinaryOperator::And, left, right) => {
+                let left = left.eval(props, filters)?;
+                if is_truthy(&left) { right.eval(props, filters) } else { Ok(left) }
+            }
+            Expression::Binary(operator, left, right) => {
+                binary(*operator, left.eval(props, filters)?, right.eval(props, filters)?)
+            }
+        }
+    }
+}
+
+/// Entry `key` of a mapping or item at index `key` of a list, `null` when there is none
+pub fn member(value: &Value, key: &Value) -> Value {
+    match (value, key) {
+        (Value::Mapping(map), key) => map.get(key).cloned().unwrap_or(Value::Null),
+        (Value::Sequence(values), Value::Int(index)) => usize::try_from(*index)
+            .ok()
+            .and_then(|index| values.get(index))
+            .cloned()
+            .unwrap_or(Value::Null),
+        _ => Value::Null,
+    }
+}
+
+/// Converts an evaluated value to the text used inside interpolated strings
+pub fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => "".to_string(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    fn as_float(self) -> f64 {
+        match self {
+            Number::Int(value) => value as f64,
+            Number::Float(value) => value,
+        }
+    }
+}
+
+/// Null counts as zero so unset counters still work, e.g. `${$count + 1}`
+fn number(value: &Value, operator: &str) -> Result<Number, String> {
+    match value {
+        Value::Null => Ok(Number::Int(0)),
+        Value::Int(value) => Ok(Number::Int(*value)),
+        Value::Float(value) => Ok(Number::Float(*value)),
+        value => Err(format!("Operator `{}` expects numbers, got {}", operator, value)),
+    }
+}
+
+fn binary(operator: BinaryOperator, left: Value, right: Value) -> Result<Value, String> {
+    use BinaryOperator::*;
+    match operator {
+        Equal => Ok(Value::Bool(equals(&left, &right))),
+        NotEqual => Ok(Value::Bool(!equals(&left, &right))),
+        Less | LessEqual | Greater | GreaterEqual => {
+            let ordering = compare(&left, &right)?;
+            Ok(Value::Bool(match operator {
+                Less => ordering == Ordering::Less,
+                LessEqual => ordering != Ordering::Greater,
+                Greater => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            }))
+        }
+        Add if left.is_string() || right.is_string() => {
+            Ok(Value::String(to_text(&left) + &to_text(&right)))
+        }
+        Add | Subtract | Multiply | Divide | Remainder => arithmetic(operator, &left, &right),
+        Coalesce | Or | And => unreachable!("Logical operators are short-circuited in eval"),
+    }
+}
+
+fn arithmetic(operator: BinaryOperator, left: &Value, right: &Value) -> Result<Value, String> {
+    use BinaryOperator::*;
+    let symbol = match operator {
+        Add => "+",
+        Subtract => "-",
+        Multiply => "*",
+        Divide => "/",
+        _ => "%",
+    };
+    let (left, right) = (number(left, symbol)?, number(right, symbol)?);
+    if matches!(operator, Divide | Remainder) && right.as_float() == 0.0 {
+        return Err("Division by zero".to_string());
+    }
+    if let (Number::Int(left), Number::Int(right)) = (left, right) {
+        let result = match operator {
+            Add => left.checked_add(right),
+            Subtract => left.checked_sub(right),
+            Multiply => left.checked_mul(right),
+            Divide if left.checked_rem(right).is_some_and(|remainder| remainder != 0) => return Ok(Value::Float(left as f64 / right as f64)),
+            Divide => left.checked_div(right),
+            _ => left.checked_rem(right),
+        };
+        return result
+            .map(Value::Int)
+            .ok_or_else(|| format!("Integer overflow in `{} {} {}`", left, symbol, right));
+    }
+    let (left, right) = (left.as_float(), right.as_float());
+    Ok(Value::Float(match operator {
+        Add => left + right,
+        Subtract => left - right,
+        Multiply => left * right,
+        Divide => left / right,
+        _ => left % right,
+    }))
+}
+
+fn equals(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            compare(left, right) == Ok(Ordering::Equal)
+        }
+        (left, right) => left == right,
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Result<Ordering, String> {
+    match (left, right) {
+        (Value::String(left), Value::String(right)) => Ok(left.cmp(right)),
+        (Value::Int(left), Value::Int(right)) => Ok(left.cmp(right)),
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            let (left, right) = (number(left, "<")?.as_float(), number(right, "<")?.as_float());
+            left.partial_cmp(&right).ok_or_else(|| "Cannot compare NaN".to_string())
+        }
+        (left, right) => Err(format!("Cannot compare {} with {}", left, right)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(Value),
+    Identifier(String),
+    Operator(&'static str),
+    OpenParen,
+    CloseParen,
+}
+
+static OPERATORS: [&str; 22] = [
+    "??", "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", ".", "[", "]",
+    "|", ",",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, char)) = chars.peek() {
+        if char.is_whitespace() {
+            chars.next();
+        } else if char.is_ascii_digit() {
+            let mut end = start;
+            while let Some(&(index, char)) = chars.peek()
+                && (char.is_ascii_digit() || char == '.') {
+                end = index + char.len_utf8();
+                chars.next();
+            }
+            let text = &source[start..end];
+            let value = if text.contains('.') {
+                text.parse().map(Value::Float).map_err(|_| format!("Invalid number `{}`", text))?
+            } else {
+                text.parse().map(Value::Int).map_err(|_| format!("Invalid number `{}`", text))?
+            };
+            tokens.push(Token::Literal(value));
+        } else if char == '\'' || char == '"' {
+            chars.next();
+            let mut text = String::new();
+            let mut closed = false;
+            while let Some((_, next)) = chars.next() {
+                match next {
+                    '\\' => text.extend(chars.next().map(|(_, escaped)| escaped)),
+                    next if next == char => {
+                        closed = true;
+                        break;
+                    }
+                    next => text.push(next),
+                }
+            }
+            if !closed {
+                return Err(format!("Unterminated string in `{}`", source));
+            }
+            tokens.push(Token::Literal(Value::String(text)));
+        } else if char == '$' || char == '_' || char.is_alphabetic() {
+            chars.next();
+            let mut end = start + char.len_utf8();
+            while let Some(&(index, char)) = chars.peek()
+                && (char == '_' || char.is_alphanumeric()) {
+                end = index + char.len_utf8();
+                chars.next();
+            }
+            let name = source[start..end].trim_start_matches('$');
+            if name.is_empty() {
+                return Err(format!("Expected a variable name after `$` in `{}`", source));
+            }
+            tokens.push(match name {
+                "true" if char != '$' => Token::Literal(Value::Bool(true)),
+                "false" if char != '$' => Token::Literal(Value::Bool(false)),
+                "null" if char != '$' => Token::Literal(Value::Null),
+                name => Token::Identifier(name.to_string()),
+            });
+        } else {
+            let operator = OPERATORS
+                .iter()
+                .find(|operator| source[start..].starts_with(**operator))
+                .ok_or_else(|| format!("Unexpected character `{}` in `{}`", char, source))?;
+            for _ in 0..operator.len() {
+                chars.next();
+            }
+            tokens.push(match *operator {
+                "(" => Token::OpenParen,
+                ")" => Token::CloseParen,
+                operator => Token::Operator(operator),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl ExpressionParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat_operator(&mut self, operators: &[&str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Operator(operator)) if operators.contains(operator) => {
+                let operator = *operator;
+                self.position += 1;
+                Some(operator)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_binary(
+        &mut self,
+        operators: &[&str],
+        operand: fn(&mut Self) -> Result<Expression, String>,
+    ) -> Result<Expression, String> {
+        let mut left = operand(self)?;
+        while let Some(operator) = self.eat_operator(operators) {
+            let right = operand(self)?;
+            let operator = match operator {
+                "??" => BinaryOperator::Coalesce,
+                "||" => BinaryOperator::Or,
+                "&&" => BinaryOperator::And,
+                "==" => BinaryOperator::Equal,
+                "!=" => BinaryOperator::NotEqual,
+                "<" => BinaryOperator::Less,
+                "<=" => BinaryOperator::LessEqual,
+                ">" => BinaryOperator::Greater,
+                ">=" => BinaryOperator::GreaterEqual,
+                "+" => BinaryOperator::Add,
+                "-" => BinaryOperator::Subtract,
+                "*" => BinaryOperator::Multiply,
+                "/" => BinaryOperator::Divide,
+                _ => BinaryOperator::Remainder,
+            };
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_coalesce(&mut self) -> Result<Expression, String> {
+        self.parse_binary(&["??"], Self::parse_or)
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, String> {
+        self.parse_binary(&["||"], Self::parse_and)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, String> {
+        self.parse_binary(&["&&"], Self::parse_equality)
+    }
+
+    fn parse_equality(&mut self) -> Result<Expression, String> {
+        self.parse_binary(&["==", "!="], Self::parse_comparison)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression, String> {
+        self.parse_binary(&["<", "<=", ">", ">="], Self::parse_additive)
+    }
+
+    fn parse_additive(&mut self) -> Result<Expression, String> {
+        self.parse_binary(&["+", "-"], Self::parse_multiplicative)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expression, String> {
+        self.parse_binary(&["*", "/", "%"], Self::parse_unary)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        match self.eat_operator(&["!", "-"]) {
+            Some("!") => Ok(Expression::Unary(UnaryOperator::Not, Box::new(self.parse_unary()?))),
+            Some(_) => Ok(Expression::Unary(UnaryOperator::Negate, Box::new(self.parse_unary()?))),
+            None => self.parse_member(),
+        }
+    }
+
+    fn parse_member(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_primary()?;
+        while let Some(operator) = self.eat_operator(&[".", "[", "|"]) {
+            if operator == "|" {
+                let Some(Token::Identifier(name)) = self.next() else {
+                    return Err("Expected a filter name after `|`".to_string());
+                };
+                let arguments = if self.peek() == Some(&Token::OpenParen) {
+                    self.position += 1;
+                    self.parse_arguments()?
+                } else {
+                    vec![]
+                };
+                expression = Expression::Pipe(Box::new(expression), name, arguments);
+                continue;
+            }
+            let key = if operator == "." {
+                match self.next() {
+                    Some(Token::Identifier(name)) => Expression::Literal(Value::String(name)),
+                    _ => return Err("Expected a name after `.`".to_string()),
+                }
+            } else {
+                let key = self.parse_coalesce()?;
+                if self.eat_operator(&["]"]).is_none() {
+                    return Err("Expected `]`".to_string());
+                }
+                key
+            };
+            expression = Expression::Member(Box::new(expression), Box::new(key));
+        }
+        Ok(expression)
+    }
+
+    /// Comma separated expressions up to the closing parenthesis
+    fn parse_arguments(&mut self) -> Result<Vec<Expression>, String> {
+        let mut arguments = vec![];
+        if self.peek() == Some(&Token::CloseParen) {
+            self.position += 1;
+            return Ok(arguments);
+        }
+        loop {
+            arguments.push(self.parse_coalesce()?);
+            match self.next() {
+                Some(Token::CloseParen) => return Ok(arguments),
+                Some(Token::Operator(",")) => {}
+                _ => return Err("Expected `,` or `)`".to_string()),
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Literal(value)) => Ok(Expression::Literal(value)),
+            Some(Token::Identifier(name)) => Ok(Expression::Variable(name)),
+            Some(Token::OpenParen) => {
+                let expression = self.parse_coalesce()?;
+                match self.next() {
+                    Some(Token::CloseParen) => Ok(expression),
+                    _ => Err("Expected `)`".to_string()),
+                }
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::filter::builtins;
+
+    fn eval(source: &str, props: &[(&str, Value)]) -> Result<Value, String> {
+        let props = props
+            .iter()
+            .map(|(key, value)| (Value::String(key.to_string()), value.clone()))
+            .collect();
+        parse(source)?.eval(&props, &builtins())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(eval("$count+1", &[("count", Value::Int(10))]), Ok(Value::Int(11)));
+        assert_eq!(eval("(1 + 2) * 3 - 4 % 3", &[]), Ok(Value::Int(8)));
+        assert_eq!(eval("7 / 2", &[]), Ok(Value::Float(3.5)));
+        assert_eq!(eval("-count", &[("count", Value::Int(2))]), Ok(Value::Int(-2)));
+        assert!(eval("1 / 0", &[]).is_err());
+        let min = [("min", Value::Int(i64::MIN))];
+        assert!(eval("$min / -1", &min).is_err_and(|error| error.contains("overflow")));
+        assert!(eval("$min % -1", &min).is_err_and(|error| error.contains("overflow")));
+        assert!(eval("-$min", &min).is_err_and(|error| error.contains("overflow")));
+    }
+
+    #[test]
+    fn test_strings_and_logic() {
+        let props = [("name", Value::String("Ana".into())), ("age", Value::Int(30))];
+        assert_eq!(eval("'Hi ' + name", &props), Ok(Value::String("Hi Ana".into())));
+        assert_eq!(eval("age >= 18 && name != \"Bob\"", &props), Ok(Value::Bool(true)));
+        assert_eq!(eval("!(age > 40) || false", &props), Ok(Value::Bool(true)));
+        assert_eq!(eval("$missing || 'default'", &[("missing", Value::Null)]), Ok(Value::String("default".into())));
+        assert!(eval("name * 2", &props).is_err());
+        assert!(eval("unknown", &props).is_err());
+    }
+
+    #[test]
+    fn test_pipes() {
+        let props = [("name", Value::String(" ana ".into())), ("items", Value::Sequence(vec![Value::Int(1), Value::Int(2)]))];
+        assert_eq!(eval("name|trim|upper + '!'", &props), Ok(Value::String("ANA!".into())));
+        assert_eq!(eval("$items|join(', ')", &props), Ok(Value::String("1, 2".into())));
+        assert_eq!(eval("($items|len) * 2", &props), Ok(Value::Int(4)));
+        assert_eq!(eval("items || name|len", &props), Ok(props[1].1.clone()));
+        assert!(eval("name|shout", &props).is_err());
+        assert!(parse("name|join(', '").is_err());
+        let expression = parse("$kind|default($fallback)|upper").unwrap();
+        assert_eq!(expression.variables(), vec!["kind", "fallback"]);
+        assert_eq!(expression.required_variables(), vec!["fallback"]);
+        assert_eq!(parse_arguments("1, 'a'").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let props = [("kind", Value::Null), ("count", Value::Int(0))];
+        assert_eq!(eval("kind ?? 'primary'", &props), Ok(Value::String("primary".into())));
+        assert_eq!(eval("$count ?? 5", &props), Ok(Value::Int(0)));
+        assert_eq!(eval("kind ?? count || 2", &props), Ok(Value::Int(2)));
+        let expression = parse("$kind ?? $default + 1").unwrap();
+        assert_eq!(expression.variables(), vec!["kind", "default"]);
+        assert_eq!(expression.required_variables(), vec!["default"]);
+    }
+
+    #[test]
+    fn test_member_access() {
+        let user = Value::Mapping(
+            [
+                (Value::String("name".into()), Value::String("Ana".into())),
+                (Value::String("tags".into()), Value::Sequence(vec![Value::String("admin".into())])),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let props = [("user", user), ("first", Value::Int(0))];
+        assert_eq!(eval("$user.name + '!'", &props), Ok(Value::String("Ana!".into())));
+        assert_eq!(eval("user.tags[first] == 'admin'", &props), Ok(Value::Bool(true)));
+        assert_eq!(eval("$user['tags'][1]", &props), Ok(Value::Null));
+        assert_eq!(eval("$user.email.domain", &props), Ok(Value::Null));
+        assert_eq!(parse("$user.tags[$first]").unwrap().variables(), vec!["user", "first"]);
+        assert!(parse("$user.").is_err());
+        assert!(parse("$user[0").is_err());
+    }
+
+    #[test]
+    fn test_split_segments() {
+        assert_eq!(
+            split_segments("/app?body=${$body+1}&x=${'}'}").unwrap(),
+            vec![
+                Segment::Text("/app?body="),
+                Segment::Expression("$body+1"),
+                Segment::Text("&x="),
+                Segment::Expression("'}'"),
+            ]
+        );
+        assert!(split_segments("${1 + ").is_err());
+        assert_eq!(whole_expression(" ${a} "), Some("a"));
+        assert_eq!(whole_expression("${a}b"), None);
+    }
+}
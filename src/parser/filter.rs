@@ -0,0 +1,307 @@
+use indexmap::IndexMap;
+use rust_yaml::Value;
+use super::expression::to_text;
+
+/// Rust code transforming a prop through a pipe, with the arguments written in parentheses.
+///
+/// ```yaml
+/// card:
+///     h2: $title|trim|upper
+///     p: "${tags|join(', ')}"
+/// ```
+pub type Filter = Box<dyn Fn(Value, &[Value]) -> Result<Value, String>>;
+
+pub type Filters = IndexMap<String, Filter>;
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+const WEEKDAYS: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+type Builtin = fn(Value, &[Value]) -> Result<Value, String>;
+
+/// Filters available to every parser
+pub fn builtins() -> Filters {
+    let filters: [(&str, Builtin); 11] = [
+        ("upper", |value, _| Ok(map_text(value, |text| text.to_uppercase()))),
+        ("lower", |value, _| Ok(map_text(value, |text| text.to_lowercase()))),
+        ("title", |value, _| Ok(map_text(value, title))),
+        ("trim", |value, _| Ok(map_text(value, |text| text.trim().to_string()))),
+        ("truncate", truncate),
+        ("json", |value, _| Ok(Value::String(json(&value)))),
+        ("urlencode", |value, _| Ok(map_text(value, urlencode))),
+        ("join", join),
+        ("len", len),
+        ("default", default),
+        ("date", date),
+    ];
+    filters
+        .into_iter()
+        .map(|(name, filter)| (name.to_string(), Box::new(filter) as Filter))
+        .collect()
+}
+
+/// Applies `function` to the text of `value`, `null` stays `null`
+fn map_text(value: Value, function: impl Fn(&str) -> String) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        value => Value::String(function(&to_text(&value))),
+    }
+}
+
+fn title(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut is_start = true;
+    for char in text.chars() {
+        if is_start {
+            result.extend(char.to_uppercase());
+        } else {
+            result.extend(char.to_lowercase());
+        }
+        is_start = char.is_whitespace() || char == '-';
+    }
+    result
+}
+
+/// `truncate(length, end = '...')` cuts text longer than `length` characters and appends `end`
+fn truncate(value: Value, arguments: &[Value]) -> Result<Value, String> {
+    let length = match arguments.first() {
+        Some(Value::Int(length)) if *length >= 0 => *length as usize,
+        _ => return Err("`truncate` expects a length, e.g. `truncate(20)`".into()),
+    };
+    let end = arguments.get(1).map(to_text).unwrap_or_else(|| "...".into());
+    Ok(map_text(value, |text| match text.char_indices().nth(length) {
+        Some((index, _)) => format!("{}{}", text[..index].trim_end(), end),
+        None => text.to_string(),
+    }))
+}
+
+fn urlencode(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => result.push(byte as char),
+            byte => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+/// `join(separator = ', ')` writes the items of a list, or the values of a mapping, as one text
+fn join(value: Value, arguments: &[Value]) -> Result<Value, String> {
+    let separator = arguments.first().map(to_text).unwrap_or_else(|| ", ".into());
+    let items: Vec<String> = match &value {
+        Value::Null => return Ok(Value::Null),
+        Value::Sequence(values) => values.iter().map(to_text).collect(),
+        Value::Mapping(map) => map.values().map(to_text).collect(),
+        value => vec![to_text(value)],
+    };
+    Ok(Value::String(items.join(&separator)))
+}
+
+/// Characters of a text or entries of a list or mapping
+fn len(value: Value, _: &[Value]) -> Result<Value, String> {
+    let len = match &value {
+        Value::Null => 0,
+        Value::Sequence(values) => values.len(),
+        Value::Mapping(map) => map.len(),
+        value => to_text(value).chars().count(),
+    };
+    Ok(Value::Int(len as i64))
+}
+
+/// `default(value)` replaces `null` and empty text, unlike a quoted `|'fallback'` that only replaces `null`
+fn default(value: Value, arguments: &[Value]) -> Result<Value, String> {
+    let is_empty = value.is_null() || value.as_str() == Some("");
+    match arguments.first() {
+        Some(fallback) if is_empty => Ok(fallback.clone()),
+        Some(_) => Ok(value),
+        None => Err("`default` expects a value, e.g. `default('none')`".into()),
+    }
+}
+
+/// `date(format = '%Y-%m-%d')` formats a Unix timestamp or an ISO 8601 date such as `2024-05-01T09:30:00`.
+///
+/// The format supports `%Y`, `%y`, `%m`, `%d`, `%e`, `%H`, `%M`, `%S`, `%B`, `%b`, `%A`, `%a` and `%%`.
+fn date(value: Value, arguments: &[Value]) -> Result<Value, String> {
+    let format = arguments.first().map(to_text).unwrap_or_else(|| "%Y-%m-%d".into());
+    let (days, seconds) = match &value {
+        Value::Null => return Ok(Value::Null),
+        Value::Int(timestamp) => (timestamp.div_euclid(86400), timestamp.rem_euclid(86400)),
+        Value::String(text) => parse_date(text).ok_or_else(|| format!("`date` cannot read `{}`", text))?,
+        value => return Err(format!("`date` expects a timestamp or an ISO 8601 date, got {}", value)),
+    };
+    let (year, month, day) = civil_from_days(days);
+    let weekday = WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+    let month_name = MONTHS[month as usize - 1];
+    let mut result = String::new();
+    let mut chars = format.chars();
+    while let Some(char) = chars.next() {
+        if char != '%' {
+            result.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => result.push_str(&year.to_string()),
+            Some('y') => result.push_str(&format!("{:02}", year.rem_euclid(100))),
+            Some('m') => result.push_str(&format!("{:02}", month)),
+            Some('d') => result.push_str(&format!("{:02}", day)),
+            Some('e') => result.push_str(&day.to_string()),
+            Some('H') => result.push_str(&format!("{:02}", seconds / 3600)),
+            Some('M') => result.push_str(&format!("{:02}", seconds / 60 % 60)),
+            Some('S') => result.push_str(&format!("{:02}", seconds % 60)),
+            Some('B') => result.push_str(month_name),
+            Some('b') => result.push_str(&month_name[..3]),
+            Some('A') => result.push_str(weekday),
+            Some('a') => result.push_str(&weekday[..3]),
+            Some('%') => result.push('%'),
+            Some(other) => return Err(format!("`date` does not support `%{}`", other)),
+            None => result.push('%'),
+        }
+    }
+    Ok(Value::String(result))
+}
+
+/// Days since 1970-01-01 and seconds since midnight of `YYYY-MM-DD` with an optional `THH:MM[:SS]`.
+/// Years go from 0 to 9999, and days and times past the end of their month or day are refused.
+fn parse_date(text: &str) -> Option<(i64, i64)> {
+    let text = text.trim();
+    let (date, time) = match text.find(['T', ' ']) {
+        Some(index) => (&text[..index], &text[index + 1..]),
+        None => (text, ""),
+    };
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    if !(0..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    let mut seconds = 0;
+    let time = time.get(..8.min(time.len())).unwrap_or("");
+    for (part, (unit, limit)) in time.split(':').zip([(3600, 24), (60, 60), (1, 60)]).filter(|(part, _)| !part.is_empty()) {
+        let value = part.get(..2)?.parse::<i64>().ok()?;
+        if !(0..limit).contains(&value) {
+            return None;
+        }
+        seconds += value * unit;
+    }
+    Some((days_from_civil(year, month, day), seconds))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Proleptic Gregorian calendar conversions, see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn json(value: &Value) -> String {
+    match value {
+        Value::Null => "null".into(),
+        Value::Bool(value) => value.to_string(),
+        Value::Int(value) => value.to_string(),
+        Value::Float(value) if value.is_finite() => value.to_string(),
+        Value::Float(_) => "null".into(),
+        Value::String(text) => json_string(text),
+        Value::Sequence(values) => format!("[{}]", values.iter().map(json).collect::<Vec<_>>().join(",")),
+        Value::Mapping(map) => format!(
+            "{{{}}}",
+            map.iter()
+                .map(|(key, value)| format!("{}:{}", json_string(&to_text(key)), json(value)))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for char in text.chars() {
+        match char {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            char if (char as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", char as u32)),
+            char => result.push(char),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(name: &str, value: Value, arguments: &[Value]) -> Result<Value, String> {
+        builtins()[name](value, arguments)
+    }
+
+    fn text(text: &str) -> Value {
+        Value::String(text.into())
+    }
+
+    #[test]
+    fn test_text_filters() {
+        assert_eq!(run("title", text("hello big-world"), &[]), Ok(text("Hello Big-World")));
+        assert_eq!(run("truncate", text("Hello world"), &[Value::Int(5)]), Ok(text("Hello...")));
+        assert_eq!(run("truncate", text("Hello"), &[Value::Int(5), text("…")]), Ok(text("Hello")));
+        assert!(run("truncate", text("Hello"), &[]).is_err());
+        assert_eq!(run("urlencode", text("a b&c/é"), &[]), Ok(text("a%20b%26c%2F%C3%A9")));
+        assert_eq!(run("upper", Value::Null, &[]), Ok(Value::Null));
+    }
+
+    #[test]
+    fn test_value_filters() {
+        let list = Value::Sequence(vec![text("a\"b"), Value::Int(1), Value::Null]);
+        assert_eq!(run("json", list.clone(), &[]), Ok(text(r#"["a\"b",1,null]"#)));
+        assert_eq!(run("join", list.clone(), &[text(" | ")]), Ok(text("a\"b | 1 | ")));
+        assert_eq!(run("len", list, &[]), Ok(Value::Int(3)));
+        assert_eq!(run("default", text(""), &[text("none")]), Ok(text("none")));
+        assert_eq!(run("default", Value::Int(0), &[text("none")]), Ok(Value::Int(0)));
+    }
+
+    #[test]
+    fn test_date() {
+        assert_eq!(run("date", Value::Int(0), &[]), Ok(text("1970-01-01")));
+        assert_eq!(
+            run("date", text("2024-02-29T09:05:07Z"), &[text("%a %e %b %Y, %H:%M:%S")]),
+            Ok(text("Thu 29 Feb 2024, 09:05:07"))
+        );
+        assert_eq!(run("date", Value::Int(1715594400), &[text("%A %d/%m/%y %H:%M")]), Ok(text("Monday 13/05/24 10:00")));
+        assert!(run("date", text("yesterday"), &[]).is_err());
+        assert_eq!(run("date", text("2000-02-29"), &[]), Ok(text("2000-02-29")));
+        for invalid in ["99999999999-01-01", "2024-02-31", "2023-02-29", "1900-02-29", "2024-04-31", "2024-05-01T24:00"] {
+            assert_eq!(run("date", text(invalid), &[]), Err(format!("`date` cannot read `{}`", invalid)));
+        }
+    }
+}
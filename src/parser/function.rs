@@ -0,0 +1,78 @@
+use indexmap::IndexMap;
+use rust_yaml::Value;
+use super::apply::apply_props;
+use super::runtime::Runtime;
+use crate::error::Error;
+
+/// Rust code usable as a component through `from: <name>`.
+///
+/// It receives the props of the call, without `body`, and a [`FunctionCall`] giving access to
+/// the caller's `body` template and to the other components.
+///
+/// ```yaml
+/// app:
+///     ol:
+///         from: users
+///         active: true
+///         body:
+///             li: $name
+/// ```
+pub type Function = Box<dyn Fn(Value, &mut FunctionCall) -> Result<Value, Error>>;
+
+/// Props of calls to each function left out of prop substitution, the function binds them itself
+pub type VerbatimProps = IndexMap<String, Vec<Value>>;
+
+/// Handle given to a [`Function`] while it runs
+pub struct FunctionCall<'r, 'a> {
+    runtime: &'r mut Runtime<'a>,
+    name: String,
+    body: Value,
+}
+
+impl<'r, 'a> FunctionCall<'r, 'a> {
+    pub(super) fn new(runtime: &'r mut Runtime<'a>, name: &str, body: Value) -> FunctionCall<'r, 'a> {
+        FunctionCall { runtime, name: name.into(), body }
+    }
+
+    /// Name the function was registered with
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The `body` given by the caller, with its props still unbound
+    pub fn body(&self) -> &Value {
+        &self.body
+    }
+
+    /// Calls another component or function with `props`
+    pub fn call(&mut self, name: &str, props: Value) -> Result<Value, Error> {
+        self.runtime.call(name, props)
+    }
+
+    /// Binds `props` into the caller's `body` and expands the result.
+    ///
+    /// A sequence renders the body once per item, e.g. once per database row.
+    /// Without a `body` the props are returned unchanged.
+    pub fn render_body(&mut self, props: Value) -> Result<Value, Error> {
+        if self.body.is_null() {
+            return Ok(props);
+        }
+        match props {
+            Value::Sequence(items) => items
+                .into_iter()
+                .map(|item| self.render_body(item))
+                .collect::<Result<Vec<Value>, Error>>()
+                .map(Value::Sequence),
+            Value::Null => self.runtime.expand(self.body.clone()),
+            props => {
+                let props = match props {
+                    Value::Mapping(_) => props,
+                    item => Value::Mapping([(Value::String("item".into()), item)].into_iter().collect()),
+                };
+                let mut body = self.body.clone();
+                apply_props(&mut body, &props, self.runtime.filters(), self.runtime.verbatim())?;
+                self.runtime.expand(body)
+            }
+        }
+    }
+}
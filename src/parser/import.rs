@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use indexmap::IndexMap;
+use rust_yaml::{Value, Yaml};
+use super::source::{self, Definition};
+use super::utils::get_entry_selector;
+use crate::error::Error;
+
+const IMPORT_KEY: &str = "$import";
+
+/// Components read from files, with the file and source each one is defined at
+pub struct Library {
+    pub components: IndexMap<Value, Value>,
+    pub definitions: IndexMap<String, Definition>,
+}
+
+/// Reads the components of `file` and of the files it imports.
+///
+/// ```yaml
+/// $import:
+///     ui: ./ui/components.yml
+/// app:
+///     from: ui.Button
+/// ```
+///
+/// Paths are relative to the importing file. Imported components are prefixed with their
+/// namespace, as are the references between them, so `Button` using `Icon` in `ui` becomes
+/// `ui.Button` using `ui.Icon`. Entry points of imported files are ignored.
+pub fn load(file: &Path, stack: &mut Vec<PathBuf>) -> Result<Library, Error> {
+    let name = file.display().to_string();
+    let input = std::fs::read_to_string(file).map_err(|error| match stack.is_empty() {
+        true => Error::from(error),
+        false => Error::import(&name, error.to_string()),
+    })?;
+    let canonical = file.canonicalize()?;
+    if let Some(start) = stack.iter().position(|loading| *loading == canonical) {
+        let cycle: Vec<String> = stack[start..]
+            .iter()
+            .chain([&canonical])
+            .map(|file| file.display().to_string())
+            .collect();
+        return Err(Error::import(&name, format!("import cycle {}", cycle.join(" > "))));
+    }
+    let value = Yaml::new()
+        .load_str(&input)
+        .map_err(|error| Error::from(error).in_file(&name))?;
+    let Value::Mapping(mut components) = value else {
+        return Err(Error::syntax("Root YAML is not a mapping"));
+    };
+    let mut definitions = source::locate_components(&input, Some(&name));
+    let Some(imports) = components.shift_remove(&Value::String(IMPORT_KEY.into())) else {
+        return Ok(Library { components, definitions });
+    };
+    let Value::Mapping(imports) = imports else {
+        return Err(Error::import(&name, "`$import` must map namespaces to files"));
+    };
+    stack.push(canonical);
+    for (namespace, path) in imports {
+        let (Some(namespace), Some(path)) = (namespace.as_str(), path.as_str()) else {
+            return Err(Error::import(&name, "`$import` must map namespaces to files"));
+        };
+        let path = file.parent().unwrap_or(Path::new("")).join(path);
+        let library = load(&path, stack)?;
+        let local: HashSet<String> = library
+            .components
+            .keys()
+            .filter_map(Value::as_str)
+            .filter(|key| get_entry_selector(key).is_none())
+            .map(|key| key.trim_start_matches('$').to_string())
+            .collect();
+        for (key, value) in library.components {
+            let Some(key) = key.as_str().filter(|key| get_entry_selector(key).is_none()) else {
+                continue;
+            };
+            let qualified = qualify(key, namespace, &local);
+            if components.contains_key(&Value::String(qualified.clone())) {
+                return Err(Error::import(
+                    &path.display().to_string(),
+                    format!("`{}` is already defined in `{}`", qualified, name),
+                ));
+            }
+            if let Some(definition) = library.definitions.get(key) {
+                definitions.insert(qualified.clone(), definition.clone());
+            }
+            components.insert(Value::String(qualified), qualify_references(value, namespace, &local));
+        }
+    }
+    stack.pop();
+    Ok(Library { components, definitions })
+}
+
+/// Reads a component from each `*.yml` or `*.yaml` file under `dir`.
+///
+/// The component is named after the path of its file: `card.yml` defines `card`,
+/// `forms/input.yml` defines `forms.input` and `$card.yml` the template `$card`.
+pub fn load_dir(dir: &Path) -> Result<Library, Error> {
+    let mut library = Library { components: IndexMap::new(), definitions: IndexMap::new() };
+    load_dir_into(dir, "", &mut library)?;
+    Ok(library)
+}
+
+fn load_dir_into(dir: &Path, prefix: &str, library: &mut Library) -> Result<(), Error> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    entries.sort();
+    for path in entries {
+        let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if stem.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            load_dir_into(&path, &format!("{}{}.", prefix, stem), library)?;
+            continue;
+        }
+        if !matches!(path.extension().and_then(|extension| extension.to_str()), Some("yml" | "yaml")) {
+            continue;
+        }
+        let file = path.display().to_string();
+        let base = stem.trim_start_matches('$');
+        let name = format!("{}{}{}", &stem[..stem.len() - base.len()], prefix, base);
+        if let Some(definition) = library.definitions.get(&name) {
+            let defined = definition.location.file.clone().unwrap_or_default();
+            return Err(Error::import(&file, format!("`{}` is already defined in `{}`", name, defined)));
+        }
+        let input = std::fs::read_to_string(&path)?;
+        let value = Yaml::new()
+            .load_str(&input)
+            .map_err(|error| Error::from(error).in_file(&file))?;
+        library.definitions.insert(name.clone(), Definition::file(&file, &input));
+        library.components.insert(Value::String(name), value);
+    }
+    Ok(())
+}
+
+/// `Button` becomes `ui.Button` and the template `$Button` becomes `$ui.Button`
+fn qualify(name: &str, namespace: &str, local: &HashSet<String>) -> String {
+    let base = name.trim_start_matches('$');
+    if !local.contains(base) {
+        return name.to_string();
+    }
+    format!("{}{}.{}", &name[..name.len() - base.len()], namespace, base)
+}
+
+/// Prefixes the names of the imported components wherever they can be referenced:
+/// `from` values, shortcut keys and bare names
+fn qualify_references(value: Value, namespace: &str, local: &HashSet<String>) -> Value {
+    match value {
+        Value::String(text) if local.contains(&text) => Value::String(qualify(&text, namespace, local)),
+        Value::Sequence(values) => Value::Sequence(
+            values
+                .into_iter()
+                .map(|value| qualify_references(value, namespace, local))
+                .collect(),
+        ),
+        Value::Mapping(map) => Value::Mapping(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Value::String(key) if local.contains(&key) => Value::String(qualify(&key, namespace, local)),
+                        key => key,
+                    };
+                    (key, qualify_references(value, namespace, local))
+                })
+                .collect(),
+        ),
+        value => value,
+    }
+}
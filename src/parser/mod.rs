@@ -1,29 +1,95 @@
 mod constants;
 mod apply;
+mod expression;
 mod utils;
 mod runtime;
+mod source;
+mod function;
+mod filter;
+mod control;
+mod schema;
+mod import;
+mod shell;
+#[cfg(feature = "sqlite")]
+mod sql;
 pub mod render;
+pub mod node;
 
-use indexmap::IndexMap;
+pub use function::{Function, FunctionCall};
+pub use filter::Filter;
+pub use schema::{Prop, PropType};
+pub use utils::parse_scalar;
+
+use std::path::Path;
+use indexmap::{IndexMap, IndexSet};
 use runtime::Runtime;
-use rust_yaml::{Error, Value, Yaml};
-use apply::clear_props;
+use rust_yaml::{Value, Yaml};
+use apply::{clear_props, get_props, get_required_props};
+use control::resolve_control_flow;
+use filter::Filters;
+use function::VerbatimProps;
+use source::Definition;
+use utils::{get_entry_selector, get_unknown_tags};
+use constants::{DEFAULT_MAX_DEPTH, RESERVED_PROPS};
+use crate::error::{Error, Location, Trace};
+
+/// A `$(<selector>)` key: the component is mounted into the elements of an HTML shell matching `selector`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub selector: String,
+    /// Key to [`Parser::call`], e.g. `$(#root)`
+    pub component: String,
+}
+
 pub struct Parser {
     components: Value,
-    functions: IndexMap<String, fn(Value) -> Value>,
+    functions: IndexMap<String, Function>,
+    filters: Filters,
+    verbatim: VerbatimProps,
+    definitions: IndexMap<String, Definition>,
+    strict: bool,
+    max_depth: usize,
 }
 
 impl Parser {
+    /// Reads the components of `file` and of the files it imports with `$import`
     pub fn load(file: &str) -> Result<Parser, Error> {
-        let input = std::fs::read_to_string(file)?;
-        Ok(Parser::parse(&input)?)
+        let library = import::load(Path::new(file), &mut vec![])?;
+        let mut parser = Parser::from_components(Value::Mapping(library.components))?;
+        parser.definitions = library.definitions;
+        Ok(parser)
+    }
+
+    /// Reads a component from each YAML file under `dir`, named after its path: `forms/input.yml`
+    /// defines `forms.input` and `$card.yml` the template `$card`
+    pub fn load_dir(dir: &str) -> Result<Parser, Error> {
+        let library = import::load_dir(Path::new(dir))?;
+        let mut parser = Parser::from_components(Value::Mapping(library.components))?;
+        parser.definitions = library.definitions;
+        Ok(parser)
     }
 
-    pub fn from(components: Value, functions: IndexMap<String, fn(Value) -> Value>) -> Result<Parser, Error> {
-        if !components.is_mapping() {
-            Err(Error::emission("Root YAML is not a mapping"))?;
+    pub fn from(components: Value, functions: IndexMap<String, Function>) -> Result<Parser, Error> {
+        let Some(components_map) = components.as_mapping() else {
+            return Err(Error::syntax("Root YAML is not a mapping"));
+        };
+        if components_map.contains_key(&Value::String("$import".into())) {
+            return Err(Error::import("$import", "imports are resolved relative to a file, use `Parser::load`"));
         }
-        Ok(Parser { components, functions })
+        for key in components_map.keys().filter_map(|key| key.as_str()) {
+            if let Some(selector) = get_entry_selector(key) {
+                shell::check_selector(selector.trim())?;
+            }
+        }
+        Ok(Parser {
+            components,
+            functions,
+            filters: filter::builtins(),
+            verbatim: VerbatimProps::new(),
+            definitions: IndexMap::new(),
+            strict: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+        })
     }
 
     pub fn from_components(components: Value) -> Result<Parser, Error> {
@@ -32,26 +98,221 @@ impl Parser {
     
     pub fn parse(input: &str) -> Result<Parser, Error> {
         let value = rust_yaml::Yaml::new().load_str(input)?;
-        Parser::from_components(value)
+        let mut parser = Parser::from_components(value)?;
+        parser.definitions = source::locate_components(input, None);
+        Ok(parser)
+    }
+
+    /// Registers a Rust function callable as a component, see [`Function`]
+    pub fn add_function<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(Value, &mut FunctionCall) -> Result<Value, Error> + 'static,
+    {
+        self.functions.insert(name.into(), Box::new(function));
     }
 
-    pub fn add_function(&mut self, name: &str, function: fn(Value) -> Value) {
-        self.functions.insert(name.into(), function);
+    pub fn add_functions<S, F>(&mut self, functions: impl IntoIterator<Item = (S, F)>)
+    where
+        S: Into<String>,
+        F: Fn(Value, &mut FunctionCall) -> Result<Value, Error> + 'static,
+    {
+        self.functions.extend(
+            functions
+                .into_iter()
+                .map(|(name, function)| (name.into(), Box::new(function) as Function)),
+        );
     }
 
-    pub fn add_functions(&mut self, functions: IndexMap<String, fn(Value) -> Value>) {
-        self.functions.extend(functions);
+    /// Leaves the `props` of calls to the function `name` out of prop substitution, for functions
+    /// binding their `$props` themselves like the `query` of `sql`
+    pub fn set_verbatim_props(&mut self, name: &str, props: &[&str]) {
+        let props = props.iter().map(|prop| Value::String(prop.to_string())).collect();
+        self.verbatim.insert(name.into(), props);
+    }
+
+    /// Registers a filter usable in pipes such as `$name|shout` or `${items|shout('!')}`, see [`Filter`].
+    /// Its `name` is made of letters, digits and `_`, not starting with a digit.
+    ///
+    /// Registering a filter changes the markup using its name as a bare fallback: once `primary`
+    /// is a filter, `$kind|primary` runs it instead of falling back to `primary`. Quoted fallbacks
+    /// such as `$kind|'primary'` are never filters.
+    ///
+    /// Built-in filters: `upper`, `lower`, `title`, `trim`, `truncate(length, end)`, `json`,
+    /// `urlencode`, `join(separator)`, `len`, `default(value)` and `date(format)`.
+    pub fn add_filter<F>(&mut self, name: &str, filter: F)
+    where
+        F: Fn(Value, &[Value]) -> Result<Value, String> + 'static,
+    {
+        self.filters.insert(name.into(), Box::new(filter));
+    }
+
+    /// Registers the `sql` function, running the `query` prop on the SQLite database at `path`.
+    ///
+    /// Props are bound as query parameters (`$name`), never interpolated in the query text, and
+    /// the caller's `body` is rendered once per row.
+    #[cfg(feature = "sqlite")]
+    pub fn add_sqlite(&mut self, path: &str) -> Result<(), Error> {
+        let connection = rusqlite::Connection::open(path)
+            .map_err(|error| Error::Io { message: format!("failed to open `{}`: {}", path, error), trace: Trace::default() })?;
+        self.add_function("sql", move |props, call| sql::run(&connection, props, call));
+        self.set_verbatim_props("sql", &["query"]);
+        Ok(())
+    }
+
+    /// In strict mode calls fail when they reference missing components, use `from` with something
+    /// that is neither a component nor an HTML element, or leave `$props` unbound. A single
+    /// [`Error::Unresolved`] lists all of them, elements inside `svg` and `math` are not checked.
+    /// Components declaring `props` also refuse the props they neither declare nor use.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// How many nested calls a recursive component can make to itself, 32 by default
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Whether `name` is a component, a template or a function
+    pub fn has_component(&self, name: &str) -> bool {
+        let components = self.components.as_mapping().unwrap();
+        components.contains_key(&Value::String(name.into()))
+            || components.contains_key(&Value::String("$".to_string() + name))
+            || self.functions.contains_key(name)
+    }
+
+    /// Props declared in the `props` block of the component `name`, see [`Prop`]
+    pub fn props(&self, name: &str) -> Result<Vec<Prop>, Error> {
+        let components = self.components.as_mapping().unwrap();
+        match components.get(&Value::String(name.into())).and_then(|component| component.as_mapping()) {
+            Some(component) => match component.get(&Value::String(schema::PROPS_KEY.into())) {
+                Some(props) => schema::read(name, props),
+                None => Ok(vec![]),
+            },
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Props an outside caller such as a request may give to `name`: the ones declared in its `props`
+    /// block or referenced as `$prop` by it or its template, reserved keys like `from` or `if` excepted.
+    /// References in verbatim props count, e.g. the parameters of a `sql` query.
+    pub fn accepted_props(&self, name: &str) -> Result<IndexSet<String>, Error> {
+        let components = self.components.as_mapping().unwrap();
+        let mut props: IndexSet<String> = self.props(name)?.into_iter().map(|prop| prop.name).collect();
+        for key in [name.to_string(), format!("${}", name)] {
+            if let Some(component) = components.get(&Value::String(key)) {
+                props.extend(get_props(component, &VerbatimProps::new()));
+            }
+        }
+        props.retain(|prop| !RESERVED_PROPS.contains(&prop.as_str()));
+        Ok(props)
+    }
+
+    /// Where the component `name` is defined, if it was parsed from YAML text
+    pub fn location(&self, name: &str) -> Option<&Location> {
+        self.definitions.get(name).map(|definition| &definition.location)
+    }
+
+    /// File the component `name` was loaded from, imported components included
+    pub fn source_file(&self, name: &str) -> Option<&str> {
+        self.location(name)?.file.as_deref()
+    }
+
+    /// The `$(<selector>)` entry points in definition order
+    pub fn entry_points(&self) -> Vec<EntryPoint> {
+        self.components
+            .as_mapping()
+            .unwrap()
+            .keys()
+            .filter_map(Value::as_str)
+            .filter_map(|key| {
+                get_entry_selector(key).map(|selector| EntryPoint {
+                    selector: selector.trim().into(),
+                    component: key.into(),
+                })
+            })
+            .collect()
+    }
+
+    /// Renders every entry point with `props` and injects it into the elements of `shell` matching its
+    /// selector. Selectors are matched against `shell` as given, not against content mounted before.
+    pub fn mount(&self, shell: &str, props: Value) -> Result<String, Error> {
+        let entry_points = self.entry_points();
+        let mut contents = Vec::with_capacity(entry_points.len());
+        for entry in &entry_points {
+            let ranges = shell::find_targets(shell, &entry.selector)?;
+            let component = self.call(&entry.component, props.clone())?;
+            contents.push((ranges, render::html(&component)));
+        }
+        let targets = entry_points
+            .iter()
+            .zip(&contents)
+            .flat_map(|(entry, (ranges, content))| {
+                ranges.iter().map(|range| (entry.selector.as_str(), range.clone(), content.as_str()))
+            })
+            .collect();
+        shell::splice(shell, targets)
     }
 
     pub fn call(&self, name: &str, props: Value) -> Result<Value, Error> {
-        let mut runtime = Runtime::build(&self.components, &self.functions);
+        self.call_with_context(name, props, Value::Null)
+    }
+
+    /// Calls `name` with `context` readable as `$ctx.<name>` by every component, on top of which
+    /// components add their `provide` values
+    pub fn call_with_context(&self, name: &str, props: Value, context: Value) -> Result<Value, Error> {
+        let mut runtime = Runtime::build(self);
+        match context {
+            Value::Mapping(context) => runtime.provide(context),
+            Value::Null => {}
+            context => return Err(Error::syntax(format!("context must be a mapping, got `{}`", context))),
+        }
         let mut value = runtime.call(name, props)?;
-        clear_props(&mut value);
+        resolve_control_flow(&mut value, true, &self.filters, &self.verbatim, &self.functions).map_err(|error| self.traced(name, error))?;
+        if self.strict {
+            self.check_resolved(name, &value).map_err(|error| self.traced(name, error))?;
+        }
+        clear_props(&mut value, &self.filters, &self.verbatim).map_err(|error| self.traced(name, error))?;
+        render::check_markup(&value).map_err(|error| self.traced(name, error))?;
         Ok(value)
     }
 
+    /// Traces an error of the final pass over the expanded `name`, once the calls are over: its
+    /// culprit is looked for in `name`, then in the first component using it
+    fn traced(&self, name: &str, error: Error) -> Error {
+        let culprit = error.culprit();
+        let location = culprit
+            .as_deref()
+            .and_then(|culprit| {
+                self.definitions
+                    .get(name)
+                    .into_iter()
+                    .chain(self.definitions.values())
+                    .find_map(|definition| definition.find(culprit))
+            })
+            .or_else(|| self.location(name).cloned());
+        error.with_trace(Trace { call_stack: vec![name.into()], location })
+    }
+
+    /// Reports every unknown component and unbound prop of the expanded `name` at once
+    fn check_resolved(&self, name: &str, value: &Value) -> Result<(), Error> {
+        let mut components = IndexSet::new();
+        if !self.has_component(name) {
+            components.insert(name.to_string());
+        }
+        components.extend(get_unknown_tags(value));
+        let props: IndexSet<String> = get_required_props(value, &self.verbatim, &self.filters).into_iter().collect();
+        if components.is_empty() && props.is_empty() {
+            return Ok(());
+        }
+        Err(Error::Unresolved {
+            components: components.into_iter().collect(),
+            props: props.into_iter().collect(),
+            trace: Trace::default(),
+        })
+    }
+
     pub fn to_yaml(&self) -> Result<String, Error> {
-        Yaml::new().dump_str(&self.components)
+        Ok(Yaml::new().dump_str(&self.components)?)
     }
     pub fn to_json(&self) -> String {
         self.components.to_string()
@@ -59,4 +320,4 @@ impl Parser {
     pub fn to_value(&self) -> Value {
         self.components.clone()
     }
-}
\ No newline at end of file
+}
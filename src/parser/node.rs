@@ -0,0 +1,183 @@
+use std::fmt;
+use rust_yaml::Value;
+use super::constants::{HEAD_KEY, IMPLICIT_HTML_COMPONENTS, PROVIDE_KEY, SLOTS_KEY, VOID_HTML_ELEMENTS};
+use super::render::write_text;
+
+/// HTML structure of an expanded component, see [`Node::from_value`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// An element, attributes without value are boolean attributes
+    Element { tag: String, attributes: Vec<(String, Option<String>)>, children: Vec<Node> },
+    /// Text, escaped when rendered
+    Text(String),
+    /// Trusted HTML written as is
+    Raw(String),
+    /// Nodes without a parent element
+    Fragment(Vec<Node>),
+    Comment(String),
+}
+
+impl Node {
+    /// Builds the tree of a value returned by [`Parser::call`](super::Parser::call).
+    ///
+    /// Mappings with `from` (or an implicit HTML tag as key) are elements and the other keys their
+    /// attributes. Attributes set to `null` or `false`, `head` entries, unused `slots` and `provide`
+    /// are dropped. Mappings with a single `raw` or `comment` key are raw HTML and comments, sequences
+    /// are fragments and scalars are text.
+    pub fn from_value(value: &Value) -> Node {
+        Node::from_view(View::of(value))
+    }
+
+    fn from_view(view: View) -> Node {
+        match view {
+            View::Element { tag, attributes, children } => Node::Element {
+                tag: tag.to_string(),
+                attributes: attributes
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value.map(|value| value.to_string())))
+                    .collect(),
+                children: children.views().flat_map(Node::from_view_flat).collect(),
+            },
+            View::Text(text) => Node::Text(text.to_string()),
+            View::Raw(text) => Node::Raw(text.to_string()),
+            View::Fragment(children) => Node::Fragment(children.views().map(Node::from_view).collect()),
+            View::Comment(text) => Node::Comment(text.to_string()),
+        }
+    }
+
+    /// The nodes of a view, the children of a fragment as siblings
+    fn from_view_flat(view: View) -> Vec<Node> {
+        match Node::from_view(view) {
+            Node::Fragment(children) => children,
+            node => vec![node],
+        }
+    }
+}
+
+/// A [`Node`], or the expanded value it is built from, seen the same way to render both.
+///
+/// Views borrow their content and only look one level down, so values are rendered without
+/// building the tree.
+#[derive(Clone)]
+pub(super) enum View<'a> {
+    Element { tag: Text<'a>, attributes: Vec<(&'a str, Option<Text<'a>>)>, children: Children<'a> },
+    Text(Text<'a>),
+    Raw(Text<'a>),
+    Fragment(Children<'a>),
+    Comment(Text<'a>),
+}
+
+/// Text of a view, the text of a node or a value written as text, see [`write_text`]
+#[derive(Clone, Copy)]
+pub(super) enum Text<'a> {
+    Str(&'a str),
+    Value(&'a Value),
+}
+
+impl Text<'_> {
+    pub(super) fn as_str(&self) -> Option<&str> {
+        match self {
+            Text::Str(text) => Some(text),
+            Text::Value(value) => value.as_str(),
+        }
+    }
+}
+
+impl fmt::Display for Text<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Text::Str(text) => f.write_str(text),
+            Text::Value(value) => write_text(value, f),
+        }
+    }
+}
+
+/// Children of a view, the values or nodes it holds
+#[derive(Clone, Copy)]
+pub(super) enum Children<'a> {
+    Values(&'a [Value]),
+    Nodes(&'a [Node]),
+}
+
+impl<'a> Children<'a> {
+    pub(super) fn views(self) -> impl Iterator<Item = View<'a>> {
+        let (values, nodes) = match self {
+            Children::Values(values) => (values, &[][..]),
+            Children::Nodes(nodes) => (&[][..], nodes),
+        };
+        values.iter().map(View::of).chain(nodes.iter().map(View::of_node))
+    }
+}
+
+impl<'a> View<'a> {
+    pub(super) fn of(value: &'a Value) -> View<'a> {
+        match value {
+            Value::Mapping(map) if map.len() == 1 && map.contains_key(&Value::String("raw".into())) => {
+                View::Raw(Text::Value(&map[&Value::String("raw".into())]))
+            }
+            Value::Mapping(map) if map.len() == 1 && map.contains_key(&Value::String("comment".into())) => {
+                View::Comment(Text::Value(&map[&Value::String("comment".into())]))
+            }
+            Value::Mapping(map) => {
+                let mut from = map.get(&Value::String("from".into()));
+                let mut body = map.get(&Value::String("body".into()));
+                let has_from = from.is_some_and(|v| !v.is_null());
+                let has_body = body.is_some_and(|v| !v.is_null());
+                let mut attributes = Vec::new();
+                for (name, value) in map {
+                    let Some(key) = name.as_str() else {
+                        continue;
+                    };
+                    if has_from && key == "from" || has_body && key == "body" || [HEAD_KEY, SLOTS_KEY, PROVIDE_KEY].contains(&key) {
+                        continue;
+                    }
+                    if !has_body && !has_from && IMPLICIT_HTML_COMPONENTS.contains(&key) {
+                        from = Some(name);
+                        body = Some(value);
+                        continue;
+                    }
+                    match value {
+                        Value::Null | Value::Bool(false) => {}
+                        Value::Bool(true) => attributes.push((key, None)),
+                        value => attributes.push((key, Some(Text::Value(value)))),
+                    }
+                }
+                match (from.filter(|v| !v.is_null()), body.filter(|v| !v.is_null())) {
+                    (None, None) => View::Fragment(Children::Values(&[])),
+                    (None, Some(body)) => View::of(body),
+                    (Some(tag), body) => {
+                        let tag = Text::Value(tag);
+                        let body = body.filter(|_| !is_void(tag));
+                        let children = Children::Values(body.map(std::slice::from_ref).unwrap_or_default());
+                        View::Element { tag, attributes, children }
+                    }
+                }
+            }
+            Value::Sequence(values) => View::Fragment(Children::Values(values)),
+            Value::Null => View::Fragment(Children::Values(&[])),
+            value => View::Text(Text::Value(value)),
+        }
+    }
+
+    pub(super) fn of_node(node: &'a Node) -> View<'a> {
+        match node {
+            Node::Element { tag, attributes, children } => View::Element {
+                tag: Text::Str(tag),
+                attributes: attributes
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_deref().map(Text::Str)))
+                    .collect(),
+                children: Children::Nodes(children),
+            },
+            Node::Text(text) => View::Text(Text::Str(text)),
+            Node::Raw(text) => View::Raw(Text::Str(text)),
+            Node::Fragment(children) => View::Fragment(Children::Nodes(children)),
+            Node::Comment(text) => View::Comment(Text::Str(text)),
+        }
+    }
+}
+
+/// Whether `tag` is an element without content nor closing tag, like `img`
+pub(super) fn is_void(tag: Text) -> bool {
+    tag.as_str().is_some_and(|tag| VOID_HTML_ELEMENTS.contains(&tag))
+}
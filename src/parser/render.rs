@@ -1,73 +1,471 @@
+use std::fmt::{self, Write};
+use std::io;
 use rust_yaml::Value;
-use super::constants::IMPLICIT_HTML_COMPONENTS;
+use indexmap::IndexMap;
+use super::constants::{HEAD_HTML_ELEMENTS, HEAD_KEY, INLINE_HTML_ELEMENTS, PREFORMATTED_HTML_ELEMENTS, RAW_TEXT_HTML_ELEMENTS};
+use super::node::{is_void, Children, Node, Text, View};
+use crate::error::Error;
 
+/// Renders an expanded component as HTML.
+///
+/// Text and attribute values are escaped. A mapping with a single `raw` key is written as is:
+///
+/// ```yaml
+/// body:
+///     raw: <strong>trusted markup</strong>
+/// ```
+///
+/// Attributes set to `true` are written without value and the ones set to `null` or `false` are omitted.
+/// Void elements such as `img` and `input` have no closing tag. The text of `script` and `style` is
+/// written as is, [`Parser::call`](super::Parser::call) checks it does not close them early.
 pub fn html(value: &Value) -> String {
+    let mut result = String::new();
+    html_to(value, &mut result).expect("writing to a String cannot fail");
+    result
+}
+
+/// Streams the HTML of an expanded component into `out`, see [`html`]
+pub fn html_to(value: &Value, out: &mut impl Write) -> fmt::Result {
+    write_html(value, out)
+}
+
+/// Layout of the HTML written by [`html_with`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Indentation of nested block elements, `None` writes everything on a single line
+    pub indent: Option<String>,
+}
+
+impl Options {
+    /// Indented output with `spaces` spaces per level
+    pub fn pretty(spaces: usize) -> Options {
+        Options { indent: Some(" ".repeat(spaces)) }
+    }
+}
+
+/// Renders an expanded component as HTML laid out following `options`.
+///
+/// When pretty printing, block elements go on their own lines and are indented, elements with
+/// only text and inline elements (`span`, `a`, `strong`...) stay on one line, and `pre`,
+/// `textarea`, `script` and `style` are written as is.
+pub fn html_with(value: &Value, options: &Options) -> String {
+    let mut result = String::new();
+    html_with_to(value, options, &mut result).expect("writing to a String cannot fail");
+    result
+}
+
+/// Streams the HTML of an expanded component into `out`, see [`html_with`]
+pub fn html_with_to(value: &Value, options: &Options, out: &mut impl Write) -> fmt::Result {
+    match &options.indent {
+        None => write_html(value, out),
+        Some(indent) => {
+            let views = flatten(Children::Values(std::slice::from_ref(value)));
+            Pretty { indent, out }.write_lines(&views, 0, true)
+        }
+    }
+}
+
+/// Renders an expanded component as a full HTML document, see [`document_with`]
+pub fn document(value: &Value) -> String {
+    document_with(value, &Options::default())
+}
+
+/// Renders an expanded component as a full HTML document laid out following `options`.
+///
+/// The `head:` entries declared by the components of the tree are hoisted into `<head>`:
+///
+/// ```yaml
+/// page:
+///     head:
+///         - title: Home
+///         - from: link
+///           rel: stylesheet
+///           href: /app.css
+///     main: Hello
+/// ```
+///
+/// A later `title`, `base` or `meta` with the same `name`, `property`, `http-equiv` or `charset`
+/// replaces an earlier one, and repeated entries are written once. A `<meta charset="utf-8">` is
+/// added unless a charset is declared.
+pub fn document_with(value: &Value, options: &Options) -> String {
+    let mut entries = IndexMap::new();
+    entries.insert("meta:charset".to_string(), meta_charset());
+    collect_head(value, &mut entries);
+    let page = Value::Mapping(IndexMap::from([
+        (Value::String("from".into()), Value::String("html".into())),
+        (Value::String("body".into()), Value::Sequence(vec![
+            Value::Mapping(IndexMap::from([
+                (Value::String("from".into()), Value::String("head".into())),
+                (Value::String("body".into()), Value::Sequence(entries.into_values().collect())),
+            ])),
+            Value::Mapping(IndexMap::from([
+                (Value::String("from".into()), Value::String("body".into())),
+                (Value::String("body".into()), value.clone()),
+            ])),
+        ])),
+    ]));
+    let separator = if options.indent.is_some() { "\n" } else { "" };
+    format!("<!DOCTYPE html>{}{}", separator, html_with(&page, options))
+}
+
+fn meta_charset() -> Value {
+    Value::Mapping(IndexMap::from([
+        (Value::String("from".into()), Value::String("meta".into())),
+        (Value::String("charset".into()), Value::String("utf-8".into())),
+    ]))
+}
+
+/// Gathers the `head:` entries of `value` in document order, keyed to drop duplicates
+fn collect_head(value: &Value, entries: &mut IndexMap<String, Value>) {
     match value {
+        Value::Sequence(values) => values.iter().for_each(|value| collect_head(value, entries)),
         Value::Mapping(map) => {
-            let mut from = map.get(&Value::String("from".to_string()));
-            let mut body = map.get(&Value::String("body".to_string()));
-            let has_from = from.map_or(false, |v| !v.is_null());
-            let has_body = body.map_or(false, |v| !v.is_null());
-            let props = {
-                let mut result = Vec::new();
-                for (key, value) in map {
-                    if !key.is_string() {
-                        continue;
-                    }
-                    let key_str = key.as_str().unwrap().to_string();
-                    if has_from && key_str == "from" || has_body && key_str == "body" {
-                        continue;
-                    }
-                    if !has_body
-                        && !has_from
-                        && IMPLICIT_HTML_COMPONENTS.contains(&key_str.as_str())
-                    {
-                        from = Some(key);
-                        body = Some(value);
-                        continue;
-                    }
-                    result.push(format!("{}=\"{}\"", key_str, html(value)).to_owned());
-                }
-                result
+            let head = map.get(&Value::String(HEAD_KEY.into()));
+            let head_entries = match head {
+                Some(Value::Sequence(values)) => values.iter().collect(),
+                Some(Value::Null) | None => vec![],
+                Some(value) => vec![value],
             };
-            let has_from = from.map_or(false, |v| !v.is_null());
-            let has_body = body.map_or(false, |v| !v.is_null());
-            if !has_body && !has_from {
-                return "".to_string();
+            for entry in head_entries {
+                let entry = head_entry(entry);
+                entries.insert(head_entry_key(&entry), entry);
+            }
+            map.iter()
+                .filter(|(key, _)| key.as_str() != Some(HEAD_KEY))
+                .for_each(|(_, value)| collect_head(value, entries));
+        }
+        _ => {}
+    }
+}
+
+/// Reads the `title: Text` shortcut of head entries as `from: title` with `body: Text`
+fn head_entry(entry: &Value) -> Value {
+    let Value::Mapping(map) = entry else {
+        return entry.clone();
+    };
+    if map.contains_key(&Value::String("from".into())) {
+        return entry.clone();
+    }
+    let tag = map.keys().find(|key| key.as_str().is_some_and(|key| HEAD_HTML_ELEMENTS.contains(&key)));
+    let Some(tag) = tag else {
+        return entry.clone();
+    };
+    let mut map = map.clone();
+    let body = map.swap_remove(tag).unwrap_or(Value::Null);
+    map.insert(Value::String("from".into()), tag.clone());
+    map.insert(Value::String("body".into()), body);
+    Value::Mapping(map)
+}
+
+/// Entries with the same key describe the same thing, the last one is kept
+fn head_entry_key(entry: &Value) -> String {
+    if let Node::Element { tag, attributes, .. } = Node::from_value(entry) {
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone().unwrap_or_default())
+        };
+        match tag.as_str() {
+            "title" | "base" => return tag,
+            "meta" => {
+                let key = ["charset", "name", "property", "http-equiv"]
+                    .into_iter()
+                    .find_map(|name| attribute(name).map(|value| (name, value)));
+                if let Some((name, value)) = key {
+                    return match name {
+                        "charset" => "meta:charset".to_string(),
+                        name => format!("meta:{}={}", name, value),
+                    };
+                }
             }
-            let props = if props.len() == 0 {
-                "".to_owned()
+            _ => {}
+        }
+    }
+    html(entry)
+}
+
+fn write_html(value: &Value, out: &mut dyn Write) -> fmt::Result {
+    write_view(View::of(value), out)
+}
+
+/// The HTML writer of values and nodes alike
+fn write_view(view: View, out: &mut dyn Write) -> fmt::Result {
+    match view {
+        View::Element { tag, attributes, children } => {
+            write_open_tag(tag, &attributes, out)?;
+            if is_void(tag) {
+                return Ok(());
+            }
+            if is_raw_text(tag) {
+                children.views().try_for_each(|child| match child {
+                    View::Text(text) => write!(out, "{}", text),
+                    child => write_view(child, out),
+                })?;
             } else {
-                " ".to_owned() + &props.join(" ")
-            };
-            match (from, body) {
-                (None, None) => "".to_string(),
-                (None, Some(body)) => html(body),
-                (Some(from), None) => format!(
-                    "<{from}{props}></{from}>",
-                    from = html(from),
-                    props = props
-                ),
-                (Some(from), Some(body)) => format!(
-                    "<{from}{props}>{body}</{from}>",
-                    from = html(from),
-                    body = html(body),
-                    props = props
-                ),
+                children.views().try_for_each(|child| write_view(child, out))?;
+            }
+            write_close_tag(tag, out)
+        }
+        View::Text(text) => write!(Escape::text(out), "{}", text),
+        View::Raw(text) => write!(out, "{}", text),
+        View::Fragment(children) => children.views().try_for_each(|child| write_view(child, out)),
+        View::Comment(text) => write!(out, "<!--{}-->", comment_text(&text.to_string())),
+    }
+}
+
+/// `text` made safe inside `<!--` and `-->`: a space goes between two `-`, before a leading `>` or
+/// `->` and after a trailing `-`, so nothing in it can end the comment
+fn comment_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    if text.starts_with('>') || text.starts_with("->") {
+        result.push(' ');
+    }
+    for char in text.chars() {
+        if char == '-' && result.ends_with('-') {
+            result.push(' ');
+        }
+        result.push(char);
+    }
+    if result.ends_with('-') {
+        result.push(' ');
+    }
+    result
+}
+
+fn write_open_tag(tag: Text, attributes: &[(&str, Option<Text>)], out: &mut dyn Write) -> fmt::Result {
+    out.write_char('<')?;
+    write!(Escape::text(out), "{}", tag)?;
+    for (name, value) in attributes {
+        write!(out, " {}", name)?;
+        if let Some(value) = value {
+            out.write_str("=\"")?;
+            write!(Escape::attribute(out), "{}", value)?;
+            out.write_char('"')?;
+        }
+    }
+    out.write_char('>')
+}
+
+fn write_close_tag(tag: Text, out: &mut dyn Write) -> fmt::Result {
+    out.write_str("</")?;
+    write!(Escape::text(out), "{}", tag)?;
+    out.write_char('>')
+}
+
+fn is_raw_text(tag: Text) -> bool {
+    tag.as_str().is_some_and(|tag| RAW_TEXT_HTML_ELEMENTS.contains(&tag))
+}
+
+/// Checks that the tags and attributes of an expanded component have valid HTML names, and that
+/// the text of its `script` and `style` elements, written unescaped, does not close them
+pub(super) fn check_markup(value: &Value) -> Result<(), Error> {
+    check_view(View::of(value))
+}
+
+fn check_view(view: View) -> Result<(), Error> {
+    match view {
+        View::Element { tag, attributes, children } => {
+            let name = tag.to_string();
+            if !is_tag_name(&name) {
+                return Err(Error::syntax(format!("`{}` is not a valid tag name", name)));
+            }
+            if let Some((attribute, _)) = attributes.iter().find(|(attribute, _)| !is_attribute_name(attribute)) {
+                return Err(Error::syntax(format!("`{}` is not a valid attribute name of <{}>", attribute, name)));
             }
+            if is_raw_text(tag) {
+                let end = format!("</{}", name);
+                let closes = children.views().any(|child| {
+                    matches!(child, View::Text(text) if text.to_string().to_ascii_lowercase().contains(&end))
+                });
+                if closes {
+                    return Err(Error::syntax(format!("the text of <{}> cannot contain `{}`", name, end)));
+                }
+            }
+            children.views().try_for_each(check_view)
         }
-        Value::Sequence(v) => v
-            .iter()
-            .map(|obj| html(obj))
-            .collect::<Vec<String>>()
-            .join(""),
-        Value::Null => "".to_string(),
-        v => {
-            if v.is_string() {
-                v.as_str().unwrap_or_default().to_string()
+        View::Fragment(children) => children.views().try_for_each(check_view),
+        _ => Ok(()),
+    }
+}
+
+/// An ASCII letter followed by letters, digits, `-`, `_`, `.` or `:`, like `div`, `my-widget` or `svg:path`
+fn is_tag_name(name: &str) -> bool {
+    name.starts_with(|char: char| char.is_ascii_alphabetic())
+        && name.chars().all(|char| char.is_ascii_alphanumeric() || matches!(char, '-' | '_' | '.' | ':'))
+}
+
+/// Anything but whitespace, controls, quotes, `<`, `>`, `/` and `=`, so `hx-get`, `@click` and `:class` are valid
+fn is_attribute_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().any(|char| {
+            char.is_whitespace() || char.is_control() || matches!(char, '"' | '\'' | '<' | '>' | '/' | '=')
+        })
+}
+
+/// Views of `children` with nested fragments replaced by their content
+fn flatten(children: Children) -> Vec<View> {
+    children
+        .views()
+        .flat_map(|view| match view {
+            View::Fragment(children) => flatten(children),
+            view => vec![view],
+        })
+        .collect()
+}
+
+fn is_inline(view: &View) -> bool {
+    match view {
+        View::Element { tag, .. } => tag.as_str().is_some_and(|tag| INLINE_HTML_ELEMENTS.contains(&tag)),
+        _ => true,
+    }
+}
+
+/// Indented writer used by [`html_with`]
+struct Pretty<'o> {
+    indent: &'o str,
+    out: &'o mut dyn Write,
+}
+
+impl Pretty<'_> {
+    /// Writes block elements on their own lines and runs of inline content on shared ones
+    fn write_lines(&mut self, views: &[View], depth: usize, is_first: bool) -> fmt::Result {
+        let mut is_first = is_first;
+        let mut index = 0;
+        while index < views.len() {
+            if !is_first {
+                self.new_line(depth)?;
+            }
+            is_first = false;
+            if is_inline(&views[index]) {
+                while index < views.len() && is_inline(&views[index]) {
+                    write_view(views[index].clone(), self.out)?;
+                    index += 1;
+                }
             } else {
-                format!("{}", v)
+                self.write_block(&views[index], depth)?;
+                index += 1;
             }
         }
+        Ok(())
+    }
+
+    fn write_block(&mut self, view: &View, depth: usize) -> fmt::Result {
+        let View::Element { tag, attributes, children } = view else {
+            return write_view(view.clone(), self.out);
+        };
+        let is_preformatted = tag.as_str().is_some_and(|tag| PREFORMATTED_HTML_ELEMENTS.contains(&tag));
+        let children = flatten(*children);
+        if is_void(*tag) || is_preformatted || children.iter().all(is_inline) {
+            return write_view(view.clone(), self.out);
+        }
+        write_open_tag(*tag, attributes, self.out)?;
+        self.new_line(depth + 1)?;
+        self.write_lines(&children, depth + 1, true)?;
+        self.new_line(depth)?;
+        write_close_tag(*tag, self.out)
+    }
+
+    fn new_line(&mut self, depth: usize) -> fmt::Result {
+        self.out.write_char('\n')?;
+        (0..depth).try_for_each(|_| self.out.write_str(self.indent))
+    }
+}
+
+/// Streams the HTML of an expanded component into a file, socket or any other byte sink
+pub fn html_to_io(value: &Value, out: &mut impl io::Write) -> io::Result<()> {
+    let mut adapter = IoWrite { out, error: None };
+    html_to(value, &mut adapter).map_err(|_| {
+        adapter.error.take().unwrap_or_else(|| io::Error::other("formatter error"))
+    })
+}
+
+/// Renders a [`Node`] tree as HTML
+pub fn html_node(node: &Node) -> String {
+    let mut result = String::new();
+    html_node_to(node, &mut result).expect("writing to a String cannot fail");
+    result
+}
+
+/// Streams the HTML of a [`Node`] tree into `out`
+pub fn html_node_to(node: &Node, out: &mut impl Write) -> fmt::Result {
+    write_view(View::of_node(node), out)
+}
+
+/// Unescaped text of a value, mappings are written as HTML
+pub(super) fn write_text(value: &Value, out: &mut dyn Write) -> fmt::Result {
+    match value {
+        Value::Null => Ok(()),
+        Value::String(text) => out.write_str(text),
+        Value::Sequence(values) => values.iter().try_for_each(|value| write_text(value, out)),
+        Value::Mapping(_) => write_html(value, out),
+        value => write!(out, "{}", value),
+    }
+}
+
+/// Writer escaping what goes through it, see [`escape_text`] and [`escape_attribute`]
+struct Escape<'w> {
+    out: &'w mut dyn Write,
+    is_attribute: bool,
+}
+
+impl<'w> Escape<'w> {
+    fn text(out: &'w mut dyn Write) -> Self {
+        Escape { out, is_attribute: false }
+    }
+
+    fn attribute(out: &'w mut dyn Write) -> Self {
+        Escape { out, is_attribute: true }
+    }
+}
+
+impl Write for Escape<'_> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        let mut start = 0;
+        for (index, char) in text.char_indices() {
+            let escaped = match char {
+                '&' => "&amp;",
+                '<' if !self.is_attribute => "&lt;",
+                '"' if self.is_attribute => "&quot;",
+                _ => continue,
+            };
+            self.out.write_str(&text[start..index])?;
+            self.out.write_str(escaped)?;
+            start = index + 1;
+        }
+        self.out.write_str(&text[start..])
+    }
+}
+
+/// `fmt::Write` over an `io::Write`, keeping the error that interrupted the rendering
+struct IoWrite<'w, W: io::Write> {
+    out: &'w mut W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> Write for IoWrite<'_, W> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.out.write_all(text.as_bytes()).map_err(|error| {
+            self.error = Some(error);
+            fmt::Error
+        })
     }
+}
+
+/// Escapes `&` and `<` so text nodes cannot open tags or entities
+pub fn escape_text(text: &str) -> String {
+    escape(text, false)
+}
+
+/// Escapes `&` and `"` so values cannot leave a double-quoted attribute
+pub fn escape_attribute(text: &str) -> String {
+    escape(text, true)
+}
+
+fn escape(text: &str, is_attribute: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    Escape { out: &mut result, is_attribute }
+        .write_str(text)
+        .expect("writing to a String cannot fail");
+    result
 }
\ No newline at end of file
@@ -2,49 +2,168 @@ use indexmap::IndexMap;
 use log::debug;
 use std::ops::Deref;
 use super::utils::is_template;
-use super::apply::apply;
+use super::apply::{apply, apply_props, get_props};
+use super::control::resolve_control_flow;
 use super::utils::{get_template_name};
-use rust_yaml::{Error, Value};
-use super::constants::IMPLICIT_HTML_COMPONENTS;
+use rust_yaml::Value;
+use super::constants::{CONTEXT_PROP, IMPLICIT_HTML_COMPONENTS, PROVIDE_KEY, SLOTS_KEY, SLOT_PROP};
+use super::filter::Filters;
+use super::function::{Function, FunctionCall, VerbatimProps};
+use super::Parser;
+use super::schema::{self, Prop};
+use super::source::Definition;
+use crate::error::{Error, Trace};
 
-pub struct Runtime<'a, 'b> {
+pub struct Runtime<'a> {
     current_component: Value,
     components: &'a Value,
-    functions: &'b IndexMap<String, fn(Value) -> Value>,
+    functions: &'a IndexMap<String, Function>,
+    filters: &'a Filters,
+    verbatim: &'a VerbatimProps,
+    definitions: &'a IndexMap<String, Definition>,
     call_stack: Vec<String>,
+    /// Props received by each call in `call_stack`, used to tell recursion from cycles
+    call_props: Vec<Value>,
+    /// Values provided by the ongoing calls, read with `$ctx.<name>`, the innermost last
+    context: Vec<IndexMap<Value, Value>>,
+    /// Whether the next component parsed is a template whose props are not all given yet, the
+    /// props of the call to the component it is the template of being bound after it
+    is_partial: bool,
+    strict: bool,
+    max_depth: usize,
 }
 
-impl Deref for Runtime<'_, '_> {
+impl Deref for Runtime<'_> {
     type Target = Value;
     fn deref(&self) -> &Self::Target {
         &self.components
     }
 }
 
-impl Runtime<'_, '_> {
-    pub fn build<'b, 'a>(components: &'a Value, functions: &'b IndexMap<String,fn(Value) -> Value>) -> Runtime<'a, 'b> {
-        components.as_mapping().expect("Root components should be a JSON");
+impl<'a> Runtime<'a> {
+    pub fn build(parser: &'a Parser) -> Runtime<'a> {
+        parser.components.as_mapping().expect("Root components should be a JSON");
         Runtime {
             current_component: Value::Null,
-            components,
-            functions,
+            components: &parser.components,
+            functions: &parser.functions,
+            filters: &parser.filters,
+            verbatim: &parser.verbatim,
+            definitions: &parser.definitions,
             call_stack: Vec::new(),
+            call_props: Vec::new(),
+            context: Vec::new(),
+            is_partial: false,
+            strict: parser.strict,
+            max_depth: parser.max_depth,
          }
     }
 
+    /// Calls a component or function.
+    ///
+    /// A component may call itself as long as the props change, up to `max_depth` nested calls.
+    /// Calling it again with the same props as an ongoing call is a cycle and fails. The call is
+    /// over once this returns, even with an error a function may recover from.
     pub fn call(&mut self, name: &str, mut props: Value) -> Result<Value, Error> {
         debug!("Calling: {} ({:?})", name, self.call_stack);
-        if self.call_stack.contains(&name.to_string()) {
-            return Ok(props);
-        }
-        if let Some(function) = self.functions.get(name) {
-            return Ok(function(props));
+        let functions = self.functions;
+        if let Some(function) = functions.get(name) {
+            return self.call_function(name, function, props);
         }
+        self.check_recursion(name, &props)?;
         self.current_component = self.instantiate_component(name);
         self.call_stack.push(name.into());
-        self.process_component_call(&mut props)?;
+        self.call_props.push(props.clone());
+        let result = self.process_component_call(&mut props).map_err(|error| self.traced(error));
+        self.call_stack.pop();
+        self.call_props.pop();
+        result.map(|_| self.current_component.clone())
+    }
+
+    /// Calls a Rust function, the `body` prop is given apart as the template for its results
+    fn call_function(&mut self, name: &str, function: &Function, mut props: Value) -> Result<Value, Error> {
+        self.check_recursion(name, &props)?;
+        self.call_stack.push(name.into());
+        self.call_props.push(props.clone());
+        let body = props
+            .as_mapping_mut()
+            .and_then(|props| props.swap_remove(&Value::String("body".into())))
+            .unwrap_or(Value::Null);
+        let result = function(props, &mut FunctionCall::new(self, name, body))
+            .map_err(|error| self.traced(error));
         self.call_stack.pop();
-        Ok(self.current_component.clone())
+        self.call_props.pop();
+        result
+    }
+
+    /// Makes `values` readable with `$ctx.<name>` by every component called from now on
+    pub fn provide(&mut self, values: IndexMap<Value, Value>) {
+        self.context.push(values);
+    }
+
+    pub fn filters(&self) -> &'a Filters {
+        self.filters
+    }
+
+    pub fn verbatim(&self) -> &'a VerbatimProps {
+        self.verbatim
+    }
+
+    /// Expands `value` as if it was the body of the current component
+    pub fn expand(&mut self, value: Value) -> Result<Value, Error> {
+        let caller = std::mem::replace(&mut self.current_component, value);
+        let result = self.parse_component();
+        let value = std::mem::replace(&mut self.current_component, caller);
+        result.map(|_| value)
+    }
+
+    fn check_recursion(&self, name: &str, props: &Value) -> Result<(), Error> {
+        let path = |start: usize| {
+            let mut path = self.call_stack[start..].to_vec();
+            path.push(name.into());
+            path
+        };
+        if let Some(start) = self.find_cycle(name, props) {
+            return Err(self.traced(Error::Recursion {
+                path: path(start),
+                message: format!("`{}` calls itself with the same props", name),
+                trace: Trace::default(),
+            }));
+        }
+        let depth = self.call_stack.iter().filter(|called| *called == name).count();
+        if depth >= self.max_depth {
+            let start = self.call_stack.iter().position(|called| called == name).unwrap_or_default();
+            return Err(self.traced(Error::Recursion {
+                path: path(start),
+                message: format!("`{}` exceeded the maximum recursion depth of {}", name, self.max_depth),
+                trace: Trace::default(),
+            }));
+        }
+        Ok(())
+    }
+
+    /// Position in the call stack of an ongoing call to `name` with the same `props`
+    fn find_cycle(&self, name: &str, props: &Value) -> Option<usize> {
+        self.call_stack
+            .iter()
+            .zip(self.call_props.iter())
+            .position(|(called, called_props)| called == name && called_props == props)
+    }
+
+    /// Sets the current call chain as the trace of `error`, located at its culprit in the innermost
+    /// component using it, or at the innermost component with a known position
+    fn traced(&self, error: Error) -> Error {
+        let definitions: Vec<&Definition> = self.call_stack
+            .iter()
+            .rev()
+            .filter_map(|name| self.definitions.get(name))
+            .collect();
+        let culprit = error.culprit();
+        let location = definitions
+            .iter()
+            .find_map(|definition| definition.find(culprit.as_deref()?))
+            .or_else(|| definitions.first().map(|definition| definition.location.clone()));
+        error.with_trace(Trace { call_stack: self.call_stack.clone(), location })
     }
 
     fn call_template(&mut self) -> Result<(), Error> {
@@ -56,6 +175,8 @@ impl Runtime<'_, '_> {
     }
 
     fn process_component_call(&mut self, props: &mut Value) -> Result<(), Error> {
+        let schema = self.check_props(props)?;
+        let slots = self.take_slots(props, &schema)?;
         let is_template = self.is_current_component_template();
         let has_template = self.has_current_component_template();
         let has_component = !self.current_component.is_null();
@@ -64,8 +185,9 @@ impl Runtime<'_, '_> {
         } else if is_template || !has_component {
             debug!("Processing call to template {:?}", self.get_current_component_name());
             debug!("Before apply props {}", self.current_component);
-            apply(&mut self.current_component, props);
-            self.parse_component()?;
+            apply(&mut self.current_component, props, self.filters, self.verbatim)?;
+            self.apply_slots(slots)?;
+            self.parse_component_with_context()?;
             if has_template {
                 self.call_template()
             } else {
@@ -74,17 +196,104 @@ impl Runtime<'_, '_> {
         } else {
             debug!("Processing call to component {:?}", self.get_current_component_name());
             if has_template {
-                self.call_template()?;
+                self.is_partial = true;
+                let result = self.call_template();
+                self.is_partial = false;
+                result?;
             }
             debug!("Before apply props {}", self.current_component);
-            apply(&mut self.current_component, props);
-            self.parse_component()?;
-            Ok(())
+            apply(&mut self.current_component, props, self.filters, self.verbatim)?;
+            self.apply_slots(slots)?;
+            self.parse_component_with_context()
         }
     }
     
-    /// Apply the `shortcut`, `from` and `composition` parsers in the correct order
+    /// Takes the `props` schema out of the current component, then checks `props` against it and
+    /// sets the defaults of the ones it or its template uses
+    fn check_props(&mut self, props: &mut Value) -> Result<Vec<Prop>, Error> {
+        let Some(schema) = self.current_component
+            .as_mapping_mut()
+            .and_then(|component| component.shift_remove(&Value::String(schema::PROPS_KEY.into()))) else {
+            return Ok(vec![]);
+        };
+        let name = self.get_current_component_name().cloned().unwrap_or_default();
+        let schema = schema::read(&name, &schema)?;
+        let mut uses = get_props(&self.current_component, self.verbatim);
+        if let Some(template) = self.get_current_component_template() {
+            uses.extend(get_props(template, self.verbatim));
+        }
+        let strict = self.strict && !self.is_current_component_template();
+        schema::validate(&name, &schema, props, &uses, strict, self.verbatim)?;
+        Ok(schema)
+    }
+
+    /// Takes the named slots out of `props`: the `slots` mapping and the props declared with `slot: true`
+    /// in `schema`
+    fn take_slots(&self, props: &mut Value, schema: &[Prop]) -> Result<IndexMap<Value, Value>, Error> {
+        let Value::Mapping(props) = props else {
+            return Ok(IndexMap::new());
+        };
+        let mut slots = match props.shift_remove(&Value::String(SLOTS_KEY.into())) {
+            Some(Value::Mapping(slots)) => slots,
+            None | Some(Value::Null) => IndexMap::new(),
+            Some(slots) => return Err(Error::syntax(format!("`slots` must map slot names to content, got `{}`", slots))),
+        };
+        for prop in schema.iter().filter(|prop| prop.slot) {
+            let key = Value::String(prop.name.clone());
+            if let Some(content) = props.shift_remove(&key) {
+                slots.insert(key, content);
+            }
+        }
+        Ok(slots)
+    }
+
+    /// Binds `$slot.<name>` in the current component to the content given by the caller, slots
+    /// the component does not use are dropped
+    fn apply_slots(&mut self, slots: IndexMap<Value, Value>) -> Result<(), Error> {
+        if !get_props(&self.current_component, self.verbatim).iter().any(|prop| prop == SLOT_PROP) {
+            return Ok(());
+        }
+        let bindings = IndexMap::from([(Value::String(SLOT_PROP.into()), Value::Mapping(slots))]);
+        apply_props(&mut self.current_component, &Value::Mapping(bindings), self.filters, self.verbatim)
+    }
+
+    /// Binds `$ctx.<name>` in the current component to the values provided by its callers, then
+    /// parses it with its own `provide` values added for the components it calls
+    fn parse_component_with_context(&mut self) -> Result<(), Error> {
+        if get_props(&self.current_component, self.verbatim).iter().any(|prop| prop == CONTEXT_PROP) {
+            let context: IndexMap<Value, Value> = self.context
+                .iter()
+                .flatten()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            let bindings = IndexMap::from([(Value::String(CONTEXT_PROP.into()), Value::Mapping(context))]);
+            apply_props(&mut self.current_component, &Value::Mapping(bindings), self.filters, self.verbatim)?;
+        }
+        let provided = match self.current_component
+            .as_mapping_mut()
+            .and_then(|component| component.shift_remove(&Value::String(PROVIDE_KEY.into()))) {
+            Some(Value::Mapping(values)) => Some(values),
+            None | Some(Value::Null) => None,
+            Some(values) => return Err(Error::syntax(format!("`provide` must map names to values, got `{}`", values))),
+        };
+        let is_providing = provided.is_some();
+        if let Some(values) = provided {
+            self.provide(values);
+        }
+        let result = self.parse_component();
+        if is_providing {
+            self.context.pop();
+        }
+        result
+    }
+
+    /// Resolve the loops and conditions, then apply the `shortcut`, `from` and `composition` parsers in the correct order.
+    ///
+    /// The props are all bound by now, so conditions and loops on unbound props are resolved as if
+    /// they were `null` and only the chosen branch gets expanded, except in a partial template.
     fn parse_component(&mut self) -> Result<(), Error> {
+        let is_final = !std::mem::take(&mut self.is_partial);
+        resolve_control_flow(&mut self.current_component, is_final, self.filters, self.verbatim, self.functions)?;
         debug!("Before parse shortcut {}", self.current_component);
         self.parse_shortcut()?;
         debug!("Before parse from {}", self.current_component);
@@ -140,10 +349,16 @@ impl Runtime<'_, '_> {
             }
             Value::Mapping(mut value_map) => {
                 debug!("Is Map");
+                // The body of a function is a template for its results, it is expanded by the function
+                let is_function_call = value_map
+                    .get(&Value::String("from".into()))
+                    .and_then(Value::as_str)
+                    .is_some_and(|from| self.functions.contains_key(from));
                 value_map = value_map
                     .into_iter()
                     .map(|(key, value)| -> Result<(Value, Value), Error> {
-                        if key == Value::String("from".into()) {
+                        if key == Value::String("from".into())
+                            || is_function_call && key == Value::String("body".into()) {
                             Ok((key, value))
                         } else {
                             Ok((key, self.parse_from_value(value)?))
@@ -158,7 +373,15 @@ impl Runtime<'_, '_> {
                     debug!("Has from");
                     let from = from.clone();
                     value_map.swap_remove(&key_from);
-                    let props = self.parse_from_value(Value::Mapping(value_map))?;
+                    let body = if is_function_call {
+                        value_map.swap_remove(&Value::String("body".into()))
+                    } else {
+                        None
+                    };
+                    let mut props = self.parse_from_value(Value::Mapping(value_map))?;
+                    if let Some(body) = body {
+                        props.as_mapping_mut().unwrap().insert(Value::String("body".into()), body);
+                    }
                     debug!("Calling {}", from);
                     self.call(&from, props)?
                 } else {
@@ -218,8 +441,13 @@ impl Runtime<'_, '_> {
                         let body = value_map.swap_remove(&Value::String("body".into())).unwrap();
                         return self.parse_shortcut_value(body);
                     }
-                    for value in value_map.values_mut(){
-                        if value.is_sequence() || value.is_mapping() {
+                    for (key, value) in value_map.iter_mut() {
+                        // Slots map names to content, each content is parsed but not the mapping itself
+                        if key.as_str() == Some(SLOTS_KEY) && let Value::Mapping(slots) = value {
+                            for slot in slots.values_mut() {
+                                *slot = self.parse_shortcut_value(slot.to_owned())?;
+                            }
+                        } else if value.is_sequence() || value.is_mapping() {
                             *value = self.parse_shortcut_value(value.to_owned())?;
                         }
                     }
@@ -274,6 +502,7 @@ impl Runtime<'_, '_> {
         match value {
             Value::String(name) => {
                 debug!("Is String");
+                // A bare name of a component being expanded is text, recursion needs an explicit `from`
                 let result = if self.components_map()
                     .contains_key(&Value::String(name.clone()))
                     &&
@@ -388,5 +617,6 @@ impl Runtime<'_, '_> {
         let name = Value::String(name.into());
         components.contains_key(&name)
             || components.contains_key(&template_name)
+            || self.functions.contains_key(name.as_str().unwrap())
     }
 }
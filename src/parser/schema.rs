@@ -0,0 +1,223 @@
+use rust_yaml::Value;
+use super::apply::get_props;
+use super::constants::RESERVED_PROPS;
+use super::expression::to_text;
+use super::function::VerbatimProps;
+use crate::error::Error;
+
+pub const PROPS_KEY: &str = "props";
+
+/// Props a component declares in its `props` block.
+///
+/// ```yaml
+/// Button:
+///     props:
+///         size:
+///             type: string
+///             enum: [small, medium, large]
+///             default: medium
+///             description: Padding and font size
+///         label: {type: string, required: true}
+///     from: button
+///     class: btn-$size
+///     body: $label
+/// ```
+///
+/// A type alone can be written as `label: string`. Props with `slot: true` are named slots: the
+/// caller's `header:` is reached with `$slot.header` instead of `$header`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prop {
+    pub name: String,
+    pub kind: Option<PropType>,
+    pub required: bool,
+    pub default: Option<Value>,
+    /// Values allowed by `enum`, any value when empty
+    pub allowed: Vec<Value>,
+    pub description: Option<String>,
+    /// Content given under this name is a named slot, reached with `$slot.<name>`
+    pub slot: bool,
+}
+
+impl Prop {
+    fn new(name: String) -> Prop {
+        Prop { name, kind: None, required: false, default: None, allowed: vec![], description: None, slot: false }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropType {
+    String,
+    Number,
+    Bool,
+    List,
+    Map,
+    /// Anything that renders: a component name, a mapping or a list
+    Component,
+}
+
+impl PropType {
+    fn parse(name: &str) -> Option<PropType> {
+        match name {
+            "string" => Some(PropType::String),
+            "number" => Some(PropType::Number),
+            "bool" => Some(PropType::Bool),
+            "list" => Some(PropType::List),
+            "map" => Some(PropType::Map),
+            "component" => Some(PropType::Component),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PropType::String => "string",
+            PropType::Number => "number",
+            PropType::Bool => "bool",
+            PropType::List => "list",
+            PropType::Map => "map",
+            PropType::Component => "component",
+        }
+    }
+
+    fn accepts(self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (PropType::String, Value::String(_))
+                | (PropType::Number, Value::Int(_) | Value::Float(_))
+                | (PropType::Bool, Value::Bool(_))
+                | (PropType::List, Value::Sequence(_))
+                | (PropType::Map, Value::Mapping(_))
+                | (PropType::Component, Value::String(_) | Value::Mapping(_) | Value::Sequence(_))
+        )
+    }
+}
+
+/// Reads the `props` block of `component`, see [`Prop`]
+pub fn read(component: &str, schema: &Value) -> Result<Vec<Prop>, Error> {
+    let Value::Mapping(schema) = schema else {
+        return Err(Error::props(component, "`props` must map prop names to their type or description"));
+    };
+    schema
+        .iter()
+        .map(|(name, spec)| {
+            let name = name
+                .as_str()
+                .ok_or_else(|| Error::props(component, format!("prop name `{}` is not a string", name)))?
+                .trim_start_matches('$')
+                .to_string();
+            let spec = match spec {
+                Value::Null => return Ok(Prop::new(name)),
+                Value::String(kind) => return Ok(Prop { kind: Some(read_type(component, &name, kind)?), ..Prop::new(name) }),
+                Value::Mapping(spec) => spec,
+                _ => return Err(Error::props(component, format!("`{}` must be a type or a mapping", name))),
+            };
+            let field = |key: &str| spec.get(&Value::String(key.into()));
+            let kind = match field("type") {
+                None | Some(Value::Null) => None,
+                Some(Value::String(kind)) => Some(read_type(component, &name, kind)?),
+                Some(kind) => return Err(Error::props(component, format!("unknown type `{}` for `{}`", kind, name))),
+            };
+            let allowed = match field("enum") {
+                None | Some(Value::Null) => vec![],
+                Some(Value::Sequence(values)) => values.clone(),
+                Some(_) => return Err(Error::props(component, format!("`enum` of `{}` must be a list", name))),
+            };
+            Ok(Prop {
+                kind,
+                required: field("required").is_some_and(|required| *required == Value::Bool(true)),
+                default: field("default").filter(|value| !value.is_null()).cloned(),
+                allowed,
+                description: field("description").and_then(Value::as_str).map(String::from),
+                slot: field("slot").is_some_and(|slot| *slot == Value::Bool(true)),
+                ..Prop::new(name)
+            })
+        })
+        .collect()
+}
+
+fn read_type(component: &str, name: &str, kind: &str) -> Result<PropType, Error> {
+    PropType::parse(kind).ok_or_else(|| {
+        Error::props(
+            component,
+            format!("unknown type `{}` for `{}`, expected string, number, bool, list, map or component", kind, name),
+        )
+    })
+}
+
+/// Sets the defaults of missing props and checks `props` against the schema of `component`,
+/// reporting every violation in one error.
+///
+/// A prop given as `null` is missing. Defaults are only set for the props the component `uses`
+/// and for slots, so a default never ends up as an HTML attribute. Values still holding unbound
+/// `$props` are left for the caller to resolve and not checked. When `strict`, props that are
+/// neither declared, used nor reserved keys such as `from` are refused.
+pub fn validate(
+    component: &str,
+    schema: &[Prop],
+    props: &mut Value,
+    uses: &[String],
+    strict: bool,
+    verbatim: &VerbatimProps,
+) -> Result<(), Error> {
+    if let Value::Sequence(items) = props {
+        return items.iter_mut().try_for_each(|item| validate(component, schema, item, uses, strict, verbatim));
+    }
+    if props.is_null() {
+        if schema.iter().all(|prop| !prop.required && prop.default.is_none()) {
+            return Ok(());
+        }
+        *props = Value::Mapping(Default::default());
+    }
+    let Value::Mapping(values) = props else {
+        return Err(Error::props(component, format!("expected a mapping of props, got `{}`", props)));
+    };
+    let mut violations = vec![];
+    if strict {
+        let unknown: Vec<&str> = values
+            .keys()
+            .filter_map(Value::as_str)
+            .filter(|name| {
+                !schema.iter().any(|prop| prop.name == *name)
+                    && !uses.iter().any(|used| used == name)
+                    && !RESERVED_PROPS.contains(name)
+                    && *name != "body"
+            })
+            .collect();
+        if !unknown.is_empty() {
+            violations.push(format!("unknown prop{} `{}`", if unknown.len() > 1 { "s" } else { "" }, unknown.join("`, `")));
+        }
+    }
+    for prop in schema {
+        let key = Value::String(prop.name.clone());
+        let value = match values.get(&key) {
+            Some(value) if !value.is_null() => value,
+            _ => match &prop.default {
+                Some(default) if prop.slot || uses.contains(&prop.name) => {
+                    values.insert(key.clone(), default.clone());
+                    &values[&key]
+                }
+                Some(_) => continue,
+                None if prop.required => {
+                    violations.push(format!("`{}` is required", prop.name));
+                    continue;
+                }
+                None => continue,
+            },
+        };
+        if !get_props(value, verbatim).is_empty() {
+            continue;
+        }
+        if let Some(kind) = prop.kind
+            && !kind.accepts(value) {
+            violations.push(format!("`{}` must be a {}, got `{}`", prop.name, kind.name(), to_text(value)));
+        } else if !prop.allowed.is_empty() && !prop.allowed.contains(value) {
+            let allowed: Vec<String> = prop.allowed.iter().map(to_text).collect();
+            violations.push(format!("`{}` must be one of `{}`, got `{}`", prop.name, allowed.join("`, `"), to_text(value)));
+        }
+    }
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::props(component, violations.join(", ")))
+    }
+}
@@ -0,0 +1,376 @@
+use std::ops::Range;
+use super::constants::VOID_HTML_ELEMENTS;
+use crate::error::Error;
+
+/// Elements whose content is text, tags inside them are not scanned
+const RAW_TEXT_ELEMENTS: [&str; 4] = ["script", "style", "textarea", "title"];
+
+/// Elements whose end tag can be left out, closed by their parent or by some following elements
+const OPTIONAL_END_ELEMENTS: [&str; 17] = [
+    "p", "li", "dt", "dd", "option", "optgroup", "tr", "td", "th", "thead", "tbody", "tfoot",
+    "colgroup", "caption", "rb", "rt", "rp",
+];
+
+/// Elements closing an open `<p>` when they start
+const CLOSING_P_ELEMENTS: [&str; 29] = [
+    "address", "article", "aside", "blockquote", "details", "div", "dl", "fieldset", "figcaption",
+    "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hgroup", "hr",
+    "main", "menu", "nav", "ol", "p", "pre", "section", "ul",
+];
+
+/// Ranges of the content of every element of `document` matching `selector`.
+///
+/// Selectors are compound: a tag, `#id`, `.class` and `[attribute]` or `[attribute=value]` parts,
+/// e.g. `main#app.dark`, combinators are rejected. Matching elements need a closing tag, unless it is optional like the one
+/// of `<li>`.
+pub fn find_targets(document: &str, selector: &str) -> Result<Vec<Range<usize>>, Error> {
+    let parsed = Selector::parse(selector).map_err(|message| Error::shell(selector, message))?;
+    let tags = scan(document);
+    let mut ranges = vec![];
+    let mut index = 0;
+    while index < tags.len() {
+        let tag = &tags[index];
+        if !tag.closing && parsed.matches(tag) {
+            if tag.self_closing || VOID_HTML_ELEMENTS.contains(&tag.name.as_str()) {
+                return Err(Error::shell(selector, format!("<{}> cannot have content", tag.name)));
+            }
+            let (end, last) = find_end(&tags, index).map_err(|message| Error::shell(selector, message))?;
+            ranges.push(tag.end..end);
+            index = last;
+        }
+        index += 1;
+    }
+    if ranges.is_empty() {
+        return Err(Error::shell(selector, "no element matches in the HTML shell"));
+    }
+    Ok(ranges)
+}
+
+/// Fails when `selector` is not a compound selector, see [`find_targets`]
+pub fn check_selector(selector: &str) -> Result<(), Error> {
+    Selector::parse(selector).map(|_| ()).map_err(|message| Error::shell(selector, message))
+}
+
+/// Replaces each range of `document` with its content, given with the selector that found it.
+/// Ranges cannot overlap.
+pub fn splice(document: &str, mut targets: Vec<(&str, Range<usize>, &str)>) -> Result<String, Error> {
+    targets.sort_by_key(|(_, range, _)| range.start);
+    let size = targets.iter().map(|(_, _, content)| content.len()).sum::<usize>();
+    let mut result = String::with_capacity(document.len() + size);
+    let mut position = 0;
+    for (selector, range, content) in targets {
+        if range.start < position {
+            return Err(Error::shell(selector, "the element is inside another mounted element"));
+        }
+        result.push_str(&document[position..range.start]);
+        result.push_str(content);
+        position = range.end;
+    }
+    result.push_str(&document[position..]);
+    Ok(result)
+}
+
+/// Where the content of the element opened at `open` ends, and the index of the last tag it holds
+/// or of its closing tag.
+///
+/// Closing tags have to match the elements they close. Elements with an optional end tag are
+/// closed by the end of their parent and by the elements starting a sibling, such as `<li>`.
+fn find_end(tags: &[Tag], open: usize) -> Result<(usize, usize), String> {
+    let mut elements = vec![tags[open].name.as_str()];
+    for (index, tag) in tags.iter().enumerate().skip(open + 1) {
+        if tag.closing {
+            let Some(depth) = elements.iter().rposition(|name| *name == tag.name) else {
+                if elements.iter().all(|name| OPTIONAL_END_ELEMENTS.contains(name)) {
+                    return Ok((tag.start, index - 1));
+                }
+                return Err(format!("</{}> does not match any open element", tag.name));
+            };
+            if let Some(unclosed) = elements[depth + 1..].iter().rfind(|name| !OPTIONAL_END_ELEMENTS.contains(name)) {
+                return Err(format!("<{}> is not closed before </{}>", unclosed, tag.name));
+            }
+            if depth == 0 {
+                return Ok((tag.start, index));
+            }
+            elements.truncate(depth);
+        } else if !tag.self_closing && !VOID_HTML_ELEMENTS.contains(&tag.name.as_str()) {
+            while let Some(last) = elements.last() && closes(last, &tag.name) {
+                if elements.len() == 1 {
+                    return Ok((tag.start, index - 1));
+                }
+                elements.pop();
+            }
+            elements.push(&tag.name);
+        }
+    }
+    Err(format!("<{}> is never closed", tags[open].name))
+}
+
+/// Whether the start of `next` closes the open element `name` whose end tag was left out
+fn closes(name: &str, next: &str) -> bool {
+    match name {
+        "p" => CLOSING_P_ELEMENTS.contains(&next),
+        "li" => next == "li",
+        "dt" | "dd" => matches!(next, "dt" | "dd"),
+        "option" => matches!(next, "option" | "optgroup"),
+        "optgroup" => next == "optgroup",
+        "tr" => next == "tr",
+        "td" | "th" => matches!(next, "td" | "th" | "tr"),
+        "thead" | "tbody" => matches!(next, "tbody" | "tfoot"),
+        "rb" | "rt" | "rp" => matches!(next, "rb" | "rt" | "rp"),
+        _ => false,
+    }
+}
+
+/// Opening or closing tag of the document, `start..end` includes the angle brackets
+struct Tag {
+    name: String,
+    attributes: Vec<(String, String)>,
+    start: usize,
+    end: usize,
+    closing: bool,
+    self_closing: bool,
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn scan(document: &str) -> Vec<Tag> {
+    let mut tags = vec![];
+    let mut position = 0;
+    while let Some(offset) = document[position..].find('<') {
+        let start = position + offset;
+        let rest = &document[start..];
+        if rest.starts_with("<!--") {
+            position = rest.find("-->").map_or(document.len(), |end| start + end + 3);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            position = rest.find('>').map_or(document.len(), |end| start + end + 1);
+        } else if let Some(tag) = parse_tag(document, start) {
+            position = tag.end;
+            if !tag.closing && RAW_TEXT_ELEMENTS.contains(&tag.name.as_str()) {
+                let closing = format!("</{}", tag.name);
+                position = find_ignore_case(&document[position..], &closing)
+                    .map_or(document.len(), |end| position + end);
+            }
+            tags.push(tag);
+        } else {
+            position = start + 1;
+        }
+    }
+    tags
+}
+
+/// Offset of the first occurrence of the ASCII `needle` in `haystack`, ignoring ASCII case
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+fn parse_tag(document: &str, start: usize) -> Option<Tag> {
+    let bytes = document.as_bytes();
+    let closing = bytes.get(start + 1) == Some(&b'/');
+    let mut position = start + 1 + closing as usize;
+    let name_start = position;
+    while position < bytes.len() && (bytes[position].is_ascii_alphanumeric() || bytes[position] == b'-') {
+        position += 1;
+    }
+    if position == name_start || !bytes[name_start].is_ascii_alphabetic() {
+        return None;
+    }
+    let name = document[name_start..position].to_ascii_lowercase();
+    let mut attributes = vec![];
+    let mut self_closing = false;
+    loop {
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        match bytes.get(position)? {
+            b'>' => break,
+            b'/' => {
+                self_closing = true;
+                position += 1;
+                continue;
+            }
+            _ => self_closing = false,
+        }
+        let key_start = position;
+        while position < bytes.len() && !matches!(bytes[position], b'=' | b'>' | b'/') && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        let key = document[key_start..position].to_ascii_lowercase();
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        let mut value = String::new();
+        if bytes.get(position) == Some(&b'=') {
+            position += 1;
+            while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            let value_start;
+            match bytes.get(position)? {
+                quote @ (b'"' | b'\'') => {
+                    value_start = position + 1;
+                    position = value_start + document[value_start..].find(*quote as char)?;
+                    value = document[value_start..position].to_string();
+                    position += 1;
+                }
+                _ => {
+                    value_start = position;
+                    while position < bytes.len() && bytes[position] != b'>' && !bytes[position].is_ascii_whitespace() {
+                        position += 1;
+                    }
+                    value = document[value_start..position].to_string();
+                }
+            }
+        }
+        attributes.push((key, value));
+    }
+    Some(Tag { name, attributes, start, end: position + 1, closing, self_closing })
+}
+
+/// Compound CSS selector, see [`find_targets`]
+struct Selector {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attributes: Vec<(String, Option<String>)>,
+}
+
+impl Selector {
+    fn parse(source: &str) -> Result<Selector, String> {
+        let source = source.trim();
+        if source.is_empty() {
+            return Err("empty selector".into());
+        }
+        let (tag, mut rest) = match source.strip_prefix('*') {
+            Some(rest) => ("", rest),
+            None => split_identifier(source),
+        };
+        let mut selector = Selector {
+            tag: (!tag.is_empty()).then(|| tag.to_ascii_lowercase()),
+            id: None,
+            classes: vec![],
+            attributes: vec![],
+        };
+        while let Some(first) = rest.chars().next() {
+            match first {
+                '#' | '.' => {
+                    let (name, next) = split_identifier(&rest[1..]);
+                    if name.is_empty() {
+                        return Err(format!("missing name after `{}`", first));
+                    }
+                    if first == '#' {
+                        selector.id = Some(name.into());
+                    } else {
+                        selector.classes.push(name.into());
+                    }
+                    rest = next;
+                }
+                '[' => {
+                    let end = rest.find(']').ok_or("missing `]`")?;
+                    let attribute = &rest[1..end];
+                    let attribute = match attribute.split_once('=') {
+                        Some((name, value)) => {
+                            let value = value.trim();
+                            let value = value
+                                .strip_prefix('"').and_then(|value| value.strip_suffix('"'))
+                                .or_else(|| value.strip_prefix('\'').and_then(|value| value.strip_suffix('\'')))
+                                .unwrap_or(value);
+                            (name.trim().to_ascii_lowercase(), Some(value.to_string()))
+                        }
+                        None => (attribute.trim().to_ascii_lowercase(), None),
+                    };
+                    selector.attributes.push(attribute);
+                    rest = &rest[end + 1..];
+                }
+                ' ' | '\t' | '\n' | '\r' | '>' | '+' | '~' => {
+                    return Err("combinators are not supported, use a compound selector such as `main#app.dark`".into());
+                }
+                _ => {
+                    return Err("only compound selectors such as `main#app.dark` are supported".into());
+                }
+            }
+        }
+        Ok(selector)
+    }
+
+    fn matches(&self, tag: &Tag) -> bool {
+        self.tag.as_ref().is_none_or(|name| *name == tag.name)
+            && self.id.as_ref().is_none_or(|id| tag.attribute("id") == Some(id))
+            && self.classes.iter().all(|class| {
+                tag.attribute("class")
+                    .is_some_and(|classes| classes.split_ascii_whitespace().any(|name| name == class))
+            })
+            && self.attributes.iter().all(|(name, value)| match value {
+                Some(value) => tag.attribute(name) == Some(value),
+                None => tag.attribute(name).is_some(),
+            })
+    }
+}
+
+fn split_identifier(source: &str) -> (&str, &str) {
+    let end = source
+        .find(|char: char| !char.is_alphanumeric() && char != '-' && char != '_')
+        .unwrap_or(source.len());
+    source.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount(document: &str, selector: &str, content: &str) -> Result<String, Error> {
+        let targets = find_targets(document, selector)?
+            .into_iter()
+            .map(|range| (selector, range, content))
+            .collect();
+        splice(document, targets)
+    }
+
+    #[test]
+    fn test_mount_replaces_content_of_matching_elements() {
+        let document = r#"<!DOCTYPE html>
+<html><head><script>if (a < b) { document.body.innerHTML = "<div id='app'></div>"; }</script></head>
+<body><!-- <div id="app"> --><main id="app" class="dark wide">Loading<div>...</div></main><img class="dark"></body></html>"#;
+        let result = mount(document, "main#app.dark", "<p>Ready</p>").unwrap();
+        assert!(result.contains(r#"<main id="app" class="dark wide"><p>Ready</p></main><img class="dark">"#));
+        assert!(result.contains(r#"<!-- <div id="app"> -->"#));
+        assert!(matches!(mount(document, ".dark", "x"), Err(Error::Shell { .. })));
+        assert!(matches!(mount(document, "#missing", "x"), Err(Error::Shell { .. })));
+        assert!(matches!(mount(document, "body main", "x"), Err(Error::Shell { .. })));
+        for selector in ["body main", "body > main", "h1+p", "h1 ~ p"] {
+            assert!(check_selector(selector).unwrap_err().to_string().contains("combinators are not supported"));
+        }
+        assert!(check_selector("main#app.dark[data-x='a b']").is_ok());
+        assert_eq!(mount("<ul><li data-x='1'></li><li data-x=2></li></ul>", "[data-x]", "a").unwrap(), "<ul><li data-x='1'>a</li><li data-x=2>a</li></ul>");
+    }
+
+    #[test]
+    fn test_mount_matches_closing_tags() {
+        let document = "<div id=app><p>Intro<ul><li>One<li>Two</ul></div><footer></footer>";
+        assert_eq!(mount(document, "#app", "x").unwrap(), "<div id=app>x</div><footer></footer>");
+        assert_eq!(mount(document, "li", "x").unwrap(), "<div id=app><p>Intro<ul><li>x<li>x</ul></div><footer></footer>");
+        assert_eq!(mount(document, "p", "x").unwrap(), "<div id=app><p>x<ul><li>One<li>Two</ul></div><footer></footer>");
+        assert_eq!(mount("<main id=app><p>a</div></main>", "main", "x").unwrap_err().to_string(), "cannot mount `main`: </div> does not match any open element");
+        assert_eq!(mount("<main id=app><span>a</main>", "main", "x").unwrap_err().to_string(), "cannot mount `main`: <span> is not closed before </main>");
+        assert!(mount("<main id=app><span>a", "main", "x").is_err());
+        let targets = vec![("#a", 8..20, "x"), ("#b", 10..12, "y")];
+        assert!(splice("<div id=a><i id=b></i></div>", targets).is_err());
+    }
+
+    #[test]
+    fn test_raw_text_ends_at_closing_tag_in_any_case() {
+        let document = "<SCRIPT>let a = '<main>';</ScRiPt><main>é</main><style>p {}</STYLE><p>x</p>";
+        assert_eq!(mount(document, "main", "y").unwrap(), "<SCRIPT>let a = '<main>';</ScRiPt><main>y</main><style>p {}</STYLE><p>x</p>");
+        assert_eq!(mount(document, "p", "y").unwrap(), "<SCRIPT>let a = '<main>';</ScRiPt><main>é</main><style>p {}</STYLE><p>y</p>");
+        assert!(mount("<script><main></main>", "main", "y").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use indexmap::IndexMap;
+use crate::error::Location;
+
+/// A component definition in its YAML source, to point errors at the key or value causing them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    /// Position of the component key, or of the file defining it
+    pub location: Location,
+    /// Position where `text` starts, right after the key
+    start: Location,
+    /// Source of the definition, up to the next component key
+    text: String,
+}
+
+impl Definition {
+    /// Definition of a component that is a whole file
+    pub fn file(file: &str, input: &str) -> Definition {
+        let location = Location { file: Some(file.into()), line: 1, column: 1 };
+        Definition { start: location.clone(), location, text: input.into() }
+    }
+
+    /// Position of the first `needle` in the definition, keys and values alike, comments excepted.
+    /// It is matched as a whole word: `count` is not found in `$counter` nor `div` in `divider`.
+    pub fn find(&self, needle: &str) -> Option<Location> {
+        if needle.is_empty() {
+            return None;
+        }
+        self.text.lines().enumerate().find_map(|(index, line)| {
+            if line.trim_start().starts_with('#') {
+                return None;
+            }
+            let (offset, _) = line.match_indices(needle).find(|(offset, _)| {
+                let before = line[..*offset].chars().next_back();
+                let after = line[offset + needle.len()..].chars().next();
+                !joins(before, needle.chars().next()) && !joins(needle.chars().next_back(), after)
+            })?;
+            let column = line[..offset].chars().count() + 1;
+            Some(Location {
+                file: self.location.file.clone(),
+                line: self.start.line + index,
+                column: if index == 0 { self.start.column + column - 1 } else { column },
+            })
+        })
+    }
+}
+
+/// Whether two adjacent characters belong to the same word
+fn joins(left: Option<char>, right: Option<char>) -> bool {
+    let is_word = |char: Option<char>| char.is_some_and(|char| char.is_alphanumeric() || char == '_' || char == '-');
+    is_word(left) && is_word(right)
+}
+
+/// Finds where each root key of a YAML document is defined, along with the text of its value.
+///
+/// Only block mappings are scanned, flow style roots (`{a: 1}`) produce no definitions.
+pub fn locate_components(input: &str, file: Option<&str>) -> IndexMap<String, Definition> {
+    let mut definitions: IndexMap<String, Definition> = IndexMap::new();
+    let mut current: Option<String> = None;
+    for (index, line) in input.lines().enumerate() {
+        let root = line.chars().next().filter(|first| {
+            !first.is_whitespace() && !matches!(first, '#' | '-' | '%' | '.' | '{' | '[')
+        });
+        match root.and_then(|_| root_key(line)) {
+            Some((key, end)) => {
+                let location = |column: usize| Location { file: file.map(String::from), line: index + 1, column };
+                let definition = Definition {
+                    location: location(1),
+                    start: location(line[..end].chars().count() + 1),
+                    text: line[end..].to_string(),
+                };
+                definitions.insert(key.clone(), definition);
+                current = Some(key);
+            }
+            None if root.is_some() => current = None,
+            None => {
+                if let Some(definition) = current.as_ref().and_then(|key| definitions.get_mut(key)) {
+                    definition.text.push('\n');
+                    definition.text.push_str(line);
+                }
+            }
+        }
+    }
+    definitions
+}
+
+/// The key of a root line and the byte offset right after its `:`
+fn root_key(line: &str) -> Option<(String, usize)> {
+    if let Some(quote) = line.chars().next().filter(|char| *char == '"' || *char == '\'') {
+        let end = line[1..].find(quote)? + 1;
+        let colon = line[end + 1..].find(':')? + end + 1;
+        return Some((line[1..end].to_string(), colon + 1));
+    }
+    line.char_indices()
+        .find(|(index, char)| {
+            *char == ':' && line[index + 1..].chars().next().is_none_or(char::is_whitespace)
+        })
+        .map(|(index, _)| (line[..index].trim_end().to_string(), index + 1))
+}
@@ -0,0 +1,79 @@
+use indexmap::IndexMap;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::fallible_iterator::FallibleIterator;
+use rusqlite::{Batch, Connection, Statement};
+use rust_yaml::Value;
+use super::function::FunctionCall;
+use crate::error::Error;
+
+/// Runs the `query` prop on `connection`, one statement after the other.
+///
+/// Parameters like `$desc`, `:desc` or `@desc` are bound from the prop with the same name,
+/// unbound ones are `NULL`. The rows of the last statement returning columns are given to the
+/// caller's `body` as mappings, one per row.
+pub fn run(connection: &Connection, props: Value, call: &mut FunctionCall) -> Result<Value, Error> {
+    let fail = |message: String| Error::function(call.name(), message);
+    let props = props.as_mapping().cloned().unwrap_or_default();
+    let query = props
+        .get(&Value::String("query".into()))
+        .and_then(Value::as_str)
+        .ok_or_else(|| fail("missing `query` prop".into()))?;
+    let mut rows = Value::Sequence(vec![]);
+    let mut batch = Batch::new(connection, query);
+    while let Some(mut statement) = batch.next().map_err(|error| fail(error.to_string()))? {
+        bind_parameters(&mut statement, &props).map_err(fail)?;
+        if statement.column_count() == 0 {
+            statement.raw_execute().map_err(|error| fail(error.to_string()))?;
+        } else {
+            rows = query_rows(&mut statement).map_err(|error| fail(error.to_string()))?;
+        }
+    }
+    call.render_body(rows)
+}
+
+fn bind_parameters(statement: &mut Statement, props: &IndexMap<Value, Value>) -> Result<(), String> {
+    for index in 1..=statement.parameter_count() {
+        let name = statement
+            .parameter_name(index)
+            .ok_or_else(|| format!("positional parameter {} is not supported, use `$name`", index))?
+            .to_string();
+        let value = match props.get(&Value::String(name[1..].into())) {
+            None | Some(Value::Null) => SqlValue::Null,
+            Some(Value::Bool(value)) => SqlValue::Integer(*value as i64),
+            Some(Value::Int(value)) => SqlValue::Integer(*value),
+            Some(Value::Float(value)) => SqlValue::Real(*value),
+            Some(Value::String(value)) => SqlValue::Text(value.clone()),
+            Some(_) => return Err(format!("`{}` must be a scalar to be bound", name)),
+        };
+        statement
+            .raw_bind_parameter(index, value)
+            .map_err(|error| error.to_string())?;
+    }
+    Ok(())
+}
+
+fn query_rows(statement: &mut Statement) -> rusqlite::Result<Value> {
+    let columns: Vec<Value> = statement
+        .column_names()
+        .into_iter()
+        .map(|name| Value::String(name.into()))
+        .collect();
+    let mut result = vec![];
+    let mut rows = statement.raw_query();
+    while let Some(row) = rows.next()? {
+        let mut mapping = IndexMap::new();
+        for (index, column) in columns.iter().enumerate() {
+            let value = match row.get_ref(index)? {
+                ValueRef::Null => Value::Null,
+                ValueRef::Integer(value) => Value::Int(value),
+                ValueRef::Real(value) => Value::Float(value),
+                ValueRef::Text(value) | ValueRef::Blob(value) => {
+                    Value::String(String::from_utf8_lossy(value).into_owned())
+                }
+            };
+            mapping.insert(column.clone(), value);
+        }
+        result.push(Value::Mapping(mapping));
+    }
+    Ok(Value::Sequence(result))
+}
@@ -1,7 +1,60 @@
+use rust_yaml::{Value, Yaml};
+use super::constants::{FOREIGN_HTML_ELEMENTS, HTML_TAGS};
+
 pub fn is_template(name: &str) -> bool {
-    name.starts_with("$")
+    name.starts_with("$") && get_entry_selector(name).is_none()
+}
+
+/// Selector of an entry point key such as `$(#root)`
+pub fn get_entry_selector(name: &str) -> Option<&str> {
+    name.strip_prefix("$(")?.strip_suffix(')')
 }
 
 pub fn get_template_name(name: &str) -> String {
     "$".to_string() + name
-}
\ No newline at end of file
+}
+
+/// Reads text given outside of YAML, such as `--prop` values and query strings, as a
+/// YAML scalar: `10` is a number, `true` a bool and `a: b` stays a string
+pub fn parse_scalar(text: &str) -> Value {
+    match Yaml::new().load_str(text) {
+        Ok(value) if !value.is_mapping() && !value.is_sequence() => value,
+        _ => Value::String(text.to_string()),
+    }
+}
+
+/// `null`, `false`, zero, empty strings, empty sequences and empty mappings are falsy, so a
+/// condition on a mapping prop such as `$user` is met only when it has a key
+pub fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Int(value) => *value != 0,
+        Value::Float(value) => *value != 0.0,
+        Value::String(value) => !value.is_empty(),
+        Value::Sequence(value) => !value.is_empty(),
+        Value::Mapping(value) => !value.is_empty(),
+    }
+}
+
+/// `from` values left after expansion that are not HTML elements.
+/// Custom elements (names with a `-`) are accepted, and so is the content of `svg` and `math`
+/// elements, made of SVG and MathML elements.
+pub fn get_unknown_tags(value: &Value) -> Vec<String> {
+    match value {
+        Value::Sequence(values) => values.iter().flat_map(get_unknown_tags).collect(),
+        Value::Mapping(index_map) => {
+            let tag = index_map.get(&Value::String("from".into())).and_then(Value::as_str);
+            if tag.is_some_and(|tag| FOREIGN_HTML_ELEMENTS.contains(&tag)) {
+                return vec![];
+            }
+            let mut tags: Vec<String> = tag
+                .filter(|tag| !HTML_TAGS.contains(tag) && !tag.contains('-'))
+                .map(|tag| vec![tag.to_string()])
+                .unwrap_or_default();
+            tags.extend(index_map.values().flat_map(get_unknown_tags));
+            tags
+        }
+        _ => vec![],
+    }
+}
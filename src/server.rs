@@ -0,0 +1,175 @@
+use std::io::Read;
+use std::net::TcpListener;
+use indexmap::{IndexMap, IndexSet};
+use log::{debug, error};
+use rust_yaml::Value;
+use crate::error::{Error, Trace};
+use crate::parser::{parse_scalar, Parser};
+use crate::render::{escape_text, html};
+
+/// Page the entry points are mounted into unless [`Server::set_shell`] gives another one.
+/// It loads no script, a shell of the application brings htmx along with its `integrity` hash.
+const DEFAULT_SHELL: &str = concat!(
+    "<!DOCTYPE html>\n",
+    "<html><head><meta charset=\"utf-8\">",
+    "</head><body><div id=\"root\"></div></body></html>\n",
+);
+
+/// Body of the responses to requests that failed on the server side, the details are only logged
+const INTERNAL_ERROR: &str = "the component failed to render";
+
+/// Largest form body [`Server::listen`] reads, in bytes
+pub const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Serves components as htmx endpoints.
+///
+/// `/name` renders the component `name`, the query string and the form body of the request are
+/// its props. Only the props it declares or references are taken, the others are dropped so a
+/// request cannot add attributes or keys such as `from` and `if`. `/` mounts the `$(<selector>)`
+/// entry points into an HTML shell, by default a page with a `<div id="root">` and no script.
+/// Errors are answered with a generic message and logged.
+///
+/// ```yaml
+/// $(#root):
+///     app: 0
+/// app:
+///     button: "Clicks: $body"
+///     hx-get: "/app?body=${$body+1}"
+/// ```
+pub struct Server {
+    parser: Parser,
+    shell: String,
+}
+
+/// HTML answer to a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Server {
+    pub fn new(parser: Parser) -> Server {
+        Server { parser, shell: DEFAULT_SHELL.into() }
+    }
+
+    /// HTML document served on `/` with the entry points mounted, see [`Parser::mount`]
+    pub fn set_shell(&mut self, shell: impl Into<String>) {
+        self.shell = shell.into();
+    }
+
+    /// Renders the component targeted by `url` (path and query string) with `form` as extra props
+    pub fn handle(&self, url: &str, form: &str) -> Response {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let name = path.trim_start_matches('/');
+        let is_page = name.is_empty();
+        if is_page && self.parser.entry_points().is_empty() {
+            return Response { status: 404, body: "no `$(<selector>)` entry point to serve".into() };
+        }
+        if !is_page && !self.parser.has_component(name) {
+            return Response { status: 404, body: escape_text(&format!("component `{}` not found", name)) };
+        }
+        let accepted = if is_page {
+            self.parser
+                .entry_points()
+                .iter()
+                .map(|entry| self.parser.accepted_props(&entry.component))
+                .collect::<Result<Vec<_>, Error>>()
+                .map(|props| props.into_iter().flatten().collect())
+        } else {
+            self.parser.accepted_props(name)
+        };
+        let accepted: IndexSet<String> = match accepted {
+            Ok(accepted) => accepted,
+            Err(error) => {
+                error!("Failed to read the props of {}: {}", name, error);
+                return Response { status: 500, body: INTERNAL_ERROR.into() };
+            }
+        };
+        let mut params = parse_params(query);
+        params.extend(parse_params(form));
+        params.retain(|key, _| {
+            let is_accepted = key.as_str().is_some_and(|key| accepted.contains(key));
+            if !is_accepted {
+                debug!("Dropped param {} not used by {}", key, name);
+            }
+            is_accepted
+        });
+        let props = if params.is_empty() { Value::Null } else { Value::Mapping(params) };
+        let result = if is_page {
+            self.parser.mount(&self.shell, props)
+        } else {
+            self.parser.call(name, props).map(|component| html(&component))
+        };
+        match result {
+            Ok(body) => Response { status: 200, body },
+            Err(error) => {
+                error!("Failed to render {}: {}", name, error);
+                Response { status: 500, body: INTERNAL_ERROR.into() }
+            }
+        }
+    }
+
+    /// Answers requests on `address`, e.g. `127.0.0.1:8080`, until the process stops, see [`Server::listen_on`]
+    pub fn listen(&self, address: &str) -> Result<(), Error> {
+        let listener = TcpListener::bind(address).map_err(|error| Error::Io {
+            message: format!("failed to listen on `{}`: {}", address, error),
+            trace: Trace::default(),
+        })?;
+        self.listen_on(listener)
+    }
+
+    /// Answers the requests of `listener` until the process stops.
+    ///
+    /// A request failing to be read or answered is logged and skipped, form bodies over
+    /// [`MAX_BODY_SIZE`] are refused.
+    pub fn listen_on(&self, listener: TcpListener) -> Result<(), Error> {
+        let server = tiny_http::Server::from_listener(listener, None).map_err(|error| Error::Io {
+            message: format!("failed to listen: {}", error),
+            trace: Trace::default(),
+        })?;
+        let content_type = tiny_http::Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap();
+        for mut request in server.incoming_requests() {
+            let mut form = String::new();
+            let response = if *request.method() != tiny_http::Method::Post {
+                self.handle(request.url(), &form)
+            } else if request.body_length().is_some_and(|length| length > MAX_BODY_SIZE) {
+                Response { status: 413, body: "request body too large".into() }
+            } else {
+                let limit = MAX_BODY_SIZE as u64 + 1;
+                match request.as_reader().take(limit).read_to_string(&mut form) {
+                    Ok(length) if length > MAX_BODY_SIZE => Response { status: 413, body: "request body too large".into() },
+                    Ok(_) => self.handle(request.url(), &form),
+                    Err(error) => {
+                        debug!("Failed to read {} {}: {}", request.method(), request.url(), error);
+                        Response { status: 400, body: "the request body cannot be read as text".into() }
+                    }
+                }
+            };
+            debug!("{} {} {}", request.method(), request.url(), response.status);
+            let response = tiny_http::Response::from_string(response.body)
+                .with_status_code(response.status)
+                .with_header(content_type.clone());
+            if let Err(error) = request.respond(response) {
+                debug!("Failed to answer: {}", error);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads `a=1&b=x` as props, values are YAML scalars and repeated keys become sequences
+fn parse_params(input: &str) -> IndexMap<Value, Value> {
+    let mut params: IndexMap<Value, Value> = IndexMap::new();
+    for (key, value) in form_urlencoded::parse(input.as_bytes()) {
+        let value = parse_scalar(&value);
+        match params.get_mut(&Value::String(key.to_string())) {
+            Some(Value::Sequence(values)) => values.push(value),
+            Some(previous) => *previous = Value::Sequence(vec![previous.clone(), value]),
+            None => {
+                params.insert(Value::String(key.into_owned()), value);
+            }
+        }
+    }
+    params
+}
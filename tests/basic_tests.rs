@@ -192,4 +192,1114 @@ c:
         c.to_string(),
         r#"{"d": 123, "e": 456}"#
     );
-}
\ No newline at end of file
+}
+#[test]
+fn test_expression_in_body() {
+    let parser = Parser::parse(
+        r#"
+box:
+  body: "${$count+1}"
+"#,
+    )
+    .unwrap();
+    let props = Parser::parse("count: 10").unwrap().to_value();
+    let component = parser.call("box", props).unwrap();
+    assert_eq!(html(&component), "11");
+}
+
+#[test]
+fn test_expression_in_attribute() {
+    let parser = Parser::parse(
+        r#"
+counter:
+  button: "Clicks: $body"
+  hx-get: "/app?body=${$body+1}"
+  class: "${count > 1 && 'many' || 'one'} item"
+"#,
+    )
+    .unwrap();
+    let props = Parser::parse("body: 2\ncount: 2").unwrap().to_value();
+    let component = parser.call("counter", props).unwrap();
+    assert_eq!(
+        html(&component),
+        r#"<button class="many item" hx-get="/app?body=3">Clicks: 2</button>"#
+    );
+}
+
+#[test]
+fn test_expression_error() {
+    let parser = Parser::parse(
+        r#"
+box:
+  body: "${$name * 2}"
+"#,
+    )
+    .unwrap();
+    let props = Parser::parse("name: Ana").unwrap().to_value();
+    assert!(parser.call("box", props).is_err());
+}
+
+#[test]
+fn test_escapes_text_and_attributes() {
+    let parser = Parser::parse(
+        r#"
+Greeting:
+  from: p
+  title: $title
+  body: Hello, $name!
+"#,
+    )
+    .unwrap();
+    let props = Parser::parse(
+        r#"
+name: "<script>alert(1)</script>"
+title: 'Say "hi" & <wave>'
+"#,
+    )
+    .unwrap()
+    .to_value();
+    let component = parser.call("Greeting", props).unwrap();
+    assert_eq!(
+        html(&component),
+        r#"<p title="Say &quot;hi&quot; &amp; <wave>">Hello, &lt;script>alert(1)&lt;/script>!</p>"#
+    );
+}
+
+#[test]
+fn test_invalid_names_and_raw_text_elements() {
+    let parser = Parser::parse(
+        r#"
+Tag:
+  from: $tag
+  body: Hi
+Attribute:
+  from: a
+  body: Link
+Script:
+  from: script
+  body: var html = "<b>" + $data;
+Style:
+  from: style
+  body: $css
+"#,
+    )
+    .unwrap();
+    let call = |name: &str, key: &str, value: &str| parser.call(name, mapping(&[(key, Value::String(value.into()))]));
+    assert_eq!(html(&call("Tag", "tag", "my-widget").unwrap()), "<my-widget>Hi</my-widget>");
+    let error = call("Tag", "tag", "img src=x onerror=alert(1)").unwrap_err();
+    assert!(error.to_string().contains("is not a valid tag name"));
+    assert_eq!(html(&call("Attribute", "hx-get", "/more").unwrap()), r#"<a hx-get="/more">Link</a>"#);
+    let error = call("Attribute", "x onclick", "alert(1)").unwrap_err();
+    assert!(error.to_string().contains("`x onclick` is not a valid attribute name of <a>"));
+    assert_eq!(
+        html(&call("Script", "data", "1 && 2").unwrap()),
+        r#"<script>var html = "<b>" + 1 && 2;</script>"#
+    );
+    let error = call("Style", "css", "</STYLE><script>alert(1)</script>").unwrap_err();
+    assert!(error.to_string().contains("cannot contain `</style`"));
+}
+
+#[test]
+fn test_raw_html_is_not_escaped() {
+    let parser = Parser::parse(
+        r#"
+Article:
+  from: div
+  body:
+    - h1: $title
+    - raw: $content
+"#,
+    )
+    .unwrap();
+    let props = Parser::parse(
+        r#"
+title: "<b>Title</b>"
+content: "<p>Trusted <em>markup</em></p>"
+"#,
+    )
+    .unwrap()
+    .to_value();
+    let component = parser.call("Article", props).unwrap();
+    assert_eq!(
+        html(&component),
+        r#"<div><h1>&lt;b>Title&lt;/b></h1><p>Trusted <em>markup</em></p></div>"#
+    );
+}
+
+#[test]
+fn test_void_elements_and_boolean_attributes() {
+    let parser = Parser::parse(
+        r#"
+Field:
+  from: form
+  body:
+    - from: img
+      src: logo.png
+      alt: $alt
+    - from: input
+      name: email
+      required: true
+      disabled: $disabled
+      checked: false
+      class: "$size $color"
+"#,
+    )
+    .unwrap();
+    let props = Parser::parse("disabled: true").unwrap().to_value();
+    let component = parser.call("Field", props).unwrap();
+    assert_eq!(
+        html(&component),
+        r#"<form><img src="logo.png"><input name="email" required disabled></form>"#
+    );
+}
+
+#[test]
+fn test_error_reports_call_path_and_location() {
+    let parser = Parser::parse(
+        r#"
+page:
+  from: card
+  count: ten
+
+card:
+  from: div
+  body: "${$count * 2}"
+"#,
+    )
+    .unwrap();
+    let error = parser.call("page", Value::Null).unwrap_err();
+    assert!(matches!(error, htymlx::Error::Expression { .. }));
+    assert_eq!(error.call_stack(), ["page", "card"]);
+    let location = error.location().unwrap();
+    assert_eq!((location.line, location.column), (8, 12));
+    assert!(error.to_string().contains("in page > card at line 8, column 12"));
+}
+
+#[test]
+fn test_strict_mode_reports_unresolved_names() {
+    let mut parser = Parser::parse(
+        r#"
+page:
+  - from: Heading
+    title: Hello
+  - from: crad
+  - from: my-widget
+  - from: svg
+    viewBox: 0 0 10 10
+    body:
+      from: circle
+      r: 5
+Heading:
+  from: h1
+  body: $titel
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        html(&parser.call("page", Value::Null).unwrap()),
+        r#"<h1 title="Hello"></h1><crad></crad><my-widget></my-widget><svg viewBox="0 0 10 10"><circle r="5"></circle></svg>"#
+    );
+    parser.set_strict(true);
+    let error = parser.call("page", Value::Null).unwrap_err();
+    assert!(matches!(
+        &error,
+        htymlx::Error::Unresolved { components, props, .. } if components == &["crad"] && props == &["titel"]
+    ));
+    assert!(error.to_string().starts_with("unknown component `crad` and unbound prop `$titel`"));
+    assert_eq!(error.location().map(|location| (location.line, location.column)), Some((5, 11)));
+    let error = parser.call("Missing", Value::Null).unwrap_err();
+    assert!(matches!(&error, htymlx::Error::Unresolved { components, .. } if components == &["Missing"]));
+    let error = parser.call("Heading", Value::Null).unwrap_err();
+    assert!(matches!(&error, htymlx::Error::Unresolved { props: names, .. } if names == &["titel"]));
+    assert_eq!(error.location().map(|location| (location.line, location.column)), Some((14, 9)));
+}
+
+#[test]
+fn test_error_location_matches_whole_names() {
+    let mut parser = Parser::parse(
+        r#"
+Heading:
+  from: h1
+  class: $titles|'plain'
+  body: $title
+"#,
+    )
+    .unwrap();
+    parser.set_strict(true);
+    let error = parser.call("Heading", Value::Null).unwrap_err();
+    assert!(matches!(&error, htymlx::Error::Unresolved { props: names, .. } if names == &["title"]));
+    assert_eq!(error.location().map(|location| (location.line, location.column)), Some((5, 9)));
+}
+
+#[test]
+fn test_recursive_component_with_different_props() {
+    let parser = Parser::parse(
+        r#"
+page:
+  from: box
+  inner:
+    from: box
+    inner:
+      from: box
+      inner: leaf
+box:
+  from: div
+  body: $inner
+"#,
+    )
+    .unwrap();
+    let component = parser.call("page", Value::Null).unwrap();
+    assert_eq!(html(&component), "<div><div><div>leaf</div></div></div>");
+}
+
+#[test]
+fn test_recursion_cycle_and_depth_errors() {
+    let mut parser = Parser::parse(
+        r#"
+a:
+  from: b
+b:
+  from: a
+countdown:
+  from: div
+  body:
+    from: countdown
+    n: "${$n - 1}"
+"#,
+    )
+    .unwrap();
+    let error = parser.call("a", Value::Null).unwrap_err();
+    assert!(matches!(&error, htymlx::Error::Recursion { path, .. } if path == &["b", "a", "b"]));
+    parser.set_max_depth(5);
+    let props = Parser::parse("n: 10").unwrap().to_value();
+    let error = parser.call("countdown", props).unwrap_err();
+    assert!(matches!(&error, htymlx::Error::Recursion { path, .. } if path.len() == 6));
+    assert!(error.to_string().contains("maximum recursion depth of 5"));
+}
+
+fn mapping(entries: &[(&str, Value)]) -> Value {
+    Value::Mapping(
+        entries
+            .iter()
+            .map(|(key, value)| (Value::String(key.to_string()), value.clone()))
+            .collect(),
+    )
+}
+
+#[test]
+fn test_function_renders_body_per_row() {
+    let mut parser = Parser::parse(
+        r#"
+app:
+  from: ol
+  body:
+    from: users
+    active: true
+    body:
+      li: $name
+      if: $active
+"#,
+    )
+    .unwrap();
+    let users = vec![("God", true), ("Adam", true), ("Eve", false)];
+    parser.add_function("users", move |props, call| {
+        let active = props.get_str("active") == Some(&Value::Bool(true));
+        let rows = users
+            .iter()
+            .filter(|(_, is_active)| *is_active == active)
+            .map(|(name, _)| mapping(&[("name", Value::String(name.to_string())), ("active", Value::Bool(true))]))
+            .collect();
+        call.render_body(Value::Sequence(rows))
+    });
+    let component = parser.call("app", Value::Null).unwrap();
+    assert_eq!(html(&component), "<ol><li>God</li><li>Adam</li></ol>");
+}
+
+#[test]
+fn test_function_calls_components_and_fails() {
+    let mut parser = Parser::parse(
+        r#"
+app:
+  from: div
+  body:
+    from: badge_for
+    user: ana
+page:
+  from: broken
+Badge:
+  from: span
+  body: $text
+"#,
+    )
+    .unwrap();
+    parser.add_functions([("badge_for", |props: Value, call: &mut htymlx::parser::FunctionCall| {
+        let user = props.get_str("user").and_then(Value::as_str).unwrap_or_default().to_uppercase();
+        call.call("Badge", mapping(&[("text", Value::String(user))]))
+    })]);
+    parser.add_function("broken", |_, call| {
+        Err(htymlx::Error::function(call.name(), "database is offline"))
+    });
+    let component = parser.call("app", Value::Null).unwrap();
+    assert_eq!(html(&component), "<div><span>ANA</span></div>");
+    let error = parser.call("page", Value::Null).unwrap_err();
+    assert!(matches!(&error, htymlx::Error::Function { name, .. } if name == "broken"));
+    assert_eq!(error.call_stack(), ["page", "broken"]);
+}
+
+#[test]
+fn test_query_prop_is_interpolated_outside_verbatim_functions() {
+    let mut parser = Parser::parse(
+        r#"
+page:
+  from: Search
+  query: $q
+Search:
+  from: p
+  body: $query
+raw:
+  from: echo
+  query: $q
+"#,
+    )
+    .unwrap();
+    parser.add_function("echo", |props, _| {
+        let query = props.get_str("query").and_then(Value::as_str).unwrap_or_default();
+        Ok(Value::String(query.replace('$', "#")))
+    });
+    parser.set_verbatim_props("echo", &["query"]);
+    let props = mapping(&[("q", Value::String("hello".into()))]);
+    assert_eq!(html(&parser.call("page", props.clone()).unwrap()), "<p>hello</p>");
+    assert_eq!(parser.call("raw", props).unwrap(), Value::String("#q".into()));
+}
+
+#[test]
+fn test_function_recovers_from_failed_call() {
+    let mut parser = Parser::parse(
+        r#"
+page:
+  from: safe
+Faulty:
+  from: span
+  body: ${$count / 0}
+Badge:
+  from: span
+  body: $text
+"#,
+    )
+    .unwrap();
+    parser.add_function("safe", |_, call| {
+        let props = mapping(&[("count", Value::Int(1))]);
+        let _ = call.call("Faulty", props.clone());
+        let text = match call.call("Faulty", props) {
+            Err(error @ htymlx::Error::Expression { .. }) => error.call_stack().join(" > "),
+            result => format!("{:?}", result),
+        };
+        call.call("Badge", mapping(&[("text", Value::String(text))]))
+    });
+    let component = parser.call("page", Value::Null).unwrap();
+    assert_eq!(html(&component), "<span>page > safe > Faulty</span>");
+}
+
+#[test]
+fn test_entry_points_are_mounted_into_shell() {
+    let parser = Parser::load("docs/examples/counter-button.yml").unwrap();
+    let entry_points: Vec<(String, String)> = parser
+        .entry_points()
+        .into_iter()
+        .map(|entry| (entry.selector, entry.component))
+        .collect();
+    assert_eq!(entry_points, [("#root".to_string(), "$(#root)".to_string())]);
+    let shell = r#"<body><main id="root"><p>Loading</p></main><div id="other"></div></body>"#;
+    assert_eq!(
+        parser.mount(shell, Value::Null).unwrap(),
+        r#"<body><main id="root"><button hx-swap="outerHTML" hx-get="/app?body=1">Clicks: 0</button></main><div id="other"></div></body>"#
+    );
+}
+
+#[test]
+fn test_entry_points_match_the_shell_as_given() {
+    let parser = Parser::parse(
+        r#"
+$(#a):
+  from: div
+  class: b
+  body: A
+$(.b): B
+"#,
+    )
+    .unwrap();
+    let shell = r#"<main id="a"></main><aside class="b"></aside>"#;
+    assert_eq!(
+        parser.mount(shell, Value::Null).unwrap(),
+        r#"<main id="a"><div class="b">A</div></main><aside class="b">B</aside>"#
+    );
+    assert!(parser.mount(r#"<main id="a"><p class="b"></p></main>"#, Value::Null).is_err());
+    let error = Parser::parse("$(main > .b): B\n").err().unwrap();
+    assert!(matches!(error, htymlx::Error::Shell { .. }));
+    assert_eq!(
+        error.to_string(),
+        "cannot mount `main > .b`: combinators are not supported, use a compound selector such as `main#app.dark`"
+    );
+}
+
+#[test]
+fn test_imports_are_namespaced_and_track_their_file() {
+    let parser = Parser::load("tests/data/imports/app.yml").unwrap();
+    let component = parser.call("app", Value::Null).unwrap();
+    assert_eq!(
+        html(&component),
+        r#"<button class="btn"><i class="icon-check"></i>Save</button><span class="badge">New</span>"#
+    );
+    assert_eq!(parser.source_file("app"), Some("tests/data/imports/app.yml"));
+    assert_eq!(parser.source_file("ui.Button"), Some("tests/data/imports/./ui/components.yml"));
+    assert_eq!(parser.source_file("ui.icons.Check"), Some("tests/data/imports/./ui/../icons.yml"));
+    assert_eq!(parser.entry_points().len(), 1);
+    assert!(!parser.has_component("ui.(#root)") && !parser.has_component("$import"));
+}
+
+#[test]
+fn test_import_cycles_and_collisions_fail() {
+    let error = Parser::load("tests/data/imports/cycle.yml").err().unwrap();
+    assert!(matches!(error, htymlx::Error::Import { .. }));
+    assert!(error.to_string().contains("import cycle"), "{}", error);
+    let error = Parser::load("tests/data/imports/collision.yml").err().unwrap();
+    assert!(error.to_string().contains("`ui.Button` is already defined"), "{}", error);
+    assert!(Parser::parse("$import:\n  ui: ./ui.yml\n").is_err());
+}
+
+#[test]
+fn test_load_dir_names_components_after_their_path() {
+    let parser = Parser::load_dir("tests/data/library").unwrap();
+    assert!(parser.has_component("card") && parser.has_component("forms.input"));
+    assert!(!parser.has_component("README"));
+    assert_eq!(parser.source_file("forms.input"), Some("tests/data/library/forms/input.yaml"));
+    let props = Parser::parse("title: Hello").unwrap().to_value();
+    let component = parser.call("card", props).unwrap();
+    assert_eq!(html(&component), r#"<div class="card"><h2>Hello</h2><input></div>"#);
+}
+
+#[test]
+fn test_node_tree_of_expanded_component() {
+    use htymlx::node::Node;
+    use htymlx::render::html_node;
+    let parser = Parser::parse(
+        r#"
+Field:
+  - comment: field --
+  - from: label
+    for: name
+    hidden: false
+    body: Name
+  - from: input
+    id: name
+    required: true
+  - raw: <hr>
+"#,
+    )
+    .unwrap();
+    let node = Node::from_value(&parser.call("Field", Value::Null).unwrap());
+    assert_eq!(
+        node,
+        Node::Fragment(vec![
+            Node::Comment("field --".into()),
+            Node::Element {
+                tag: "label".into(),
+                attributes: vec![("for".into(), Some("name".into()))],
+                children: vec![Node::Text("Name".into())],
+            },
+            Node::Element {
+                tag: "input".into(),
+                attributes: vec![("id".into(), Some("name".into())), ("required".into(), None)],
+                children: vec![],
+            },
+            Node::Raw("<hr>".into()),
+        ])
+    );
+    assert_eq!(
+        html_node(&node),
+        r#"<!--field - - --><label for="name">Name</label><input id="name" required><hr>"#
+    );
+}
+
+#[test]
+fn test_comment_text_cannot_end_the_comment() {
+    use htymlx::node::Node;
+    use htymlx::render::html_node;
+    let comment = |text: &str| html_node(&Node::Comment(text.into()));
+    assert_eq!(comment("a--->b"), "<!--a- - ->b-->");
+    assert_eq!(comment(">b"), "<!-- >b-->");
+    assert_eq!(comment("->b"), "<!-- ->b-->");
+    assert_eq!(comment("<!--a--!>"), "<!--<!- -a- -!>-->");
+    assert_eq!(comment("a-"), "<!--a- -->");
+    assert_eq!(comment("a - b"), "<!--a - b-->");
+}
+
+#[test]
+fn test_streaming_renderer_matches_html() {
+    use htymlx::render::{html_to, html_to_io};
+    let parser = Parser::load("tests/data/CvExample.yaml").unwrap();
+    let component = parser.call("document", Value::Null).unwrap();
+    let mut text = String::new();
+    html_to(&component, &mut text).unwrap();
+    assert_eq!(text, html(&component));
+    assert!(text.len() > 8);
+    let mut bytes = Vec::new();
+    html_to_io(&component, &mut bytes).unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), text);
+    let mut full = [0u8; 8];
+    let error = html_to_io(&component, &mut &mut full[..]).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::WriteZero);
+}
+
+#[test]
+fn test_pretty_output() {
+    use htymlx::render::{html_with, Options};
+    let parser = Parser::parse(
+        r#"
+page:
+  - h1: Title
+  - from: main
+    body:
+      - from: p
+        body:
+          - "Hello "
+          - from: strong
+            body: World
+      - ul:
+          - li: One
+          - li:
+              - a: Two
+              - from: ol
+                body:
+                  li: Nested
+      - from: pre
+        body: "line 1\n  line 2"
+      - from: br
+      - span: Inline
+"#,
+    )
+    .unwrap();
+    let component = parser.call("page", Value::Null).unwrap();
+    assert_eq!(
+        html_with(&component, &Options::pretty(2)),
+        concat!(
+            "<h1>Title</h1>\n",
+            "<main>\n",
+            "  <p>Hello <strong>World</strong></p>\n",
+            "  <ul>\n",
+            "    <li>One</li>\n",
+            "    <li>\n",
+            "      <a>Two</a>\n",
+            "      <ol>\n",
+            "        <li>Nested</li>\n",
+            "      </ol>\n",
+            "    </li>\n",
+            "  </ul>\n",
+            "  <pre>line 1\n  line 2</pre>\n",
+            "  <br><span>Inline</span>\n",
+            "</main>",
+        )
+    );
+    assert_eq!(html_with(&component, &Options::default()), html(&component));
+}
+
+#[test]
+fn test_document_hoists_head_entries() {
+    use htymlx::render::{document, document_with, Options};
+    let parser = Parser::parse(
+        r#"
+layout:
+  from: main
+  head:
+    - title: Site
+    - from: link
+      rel: stylesheet
+      href: /app.css
+  body: $content
+card:
+  from: article
+  head:
+    from: link
+    rel: stylesheet
+    href: /app.css
+  body: $text
+page:
+  from: layout
+  head:
+    - title: $title
+    - from: meta
+      name: description
+      content: About $title
+  content:
+    - from: card
+      text: One
+    - from: card
+      text: Two
+"#,
+    )
+    .unwrap();
+    let props = Parser::parse("title: Home").unwrap().to_value();
+    let component = parser.call("page", props).unwrap();
+    assert_eq!(html(&component), "<main><article>One</article><article>Two</article></main>");
+    assert_eq!(
+        document(&component),
+        concat!(
+            r#"<!DOCTYPE html><html><head><meta charset="utf-8"><title>Home</title>"#,
+            r#"<link rel="stylesheet" href="/app.css"><meta name="description" content="About Home"></head>"#,
+            "<body><main><article>One</article><article>Two</article></main></body></html>",
+        )
+    );
+    assert!(document_with(&component, &Options::pretty(2)).starts_with("<!DOCTYPE html>\n<html>\n  <head>\n"));
+}
+
+#[test]
+fn test_if_and_else() {
+    let parser = Parser::parse(
+        r#"
+badge:
+  from: span
+  if: $count
+  else:
+    from: em
+    body: No messages
+  body: $count messages
+menu:
+  from: ul
+  body:
+    - li: Home
+    - from: li
+      if: $admin
+      body: Admin
+    - from: li
+      if: ${$count > 1}
+      body: Many
+"#,
+    )
+    .unwrap();
+    let render = |name: &str, props: &str| {
+        let props = Parser::parse(props).unwrap().to_value();
+        html(&parser.call(name, props).unwrap())
+    };
+    assert_eq!(render("badge", "count: 3"), "<span>3 messages</span>");
+    assert_eq!(render("badge", "count: 0"), "<em>No messages</em>");
+    assert_eq!(render("menu", "count: 2"), "<ul><li>Home</li><li>Many</li></ul>");
+    assert_eq!(render("menu", "admin: true\ncount: 1"), "<ul><li>Home</li><li>Admin</li></ul>");
+    assert_eq!(render("menu", "admin: ''\ncount: 0"), "<ul><li>Home</li></ul>");
+    assert_eq!(render("menu", "admin: {}\ncount: 0"), "<ul><li>Home</li></ul>");
+    assert_eq!(render("menu", "admin: []\ncount: 0"), "<ul><li>Home</li></ul>");
+    assert_eq!(render("menu", "admin:\n  role: owner\ncount: 0"), "<ul><li>Home</li><li>Admin</li></ul>");
+}
+
+#[test]
+fn test_if_expands_only_the_chosen_branch() {
+    let mut parser = Parser::parse(
+        r#"
+page:
+  from: div
+  body:
+    from: audit
+    if: $admin
+    else:
+      p: Guest
+$card:
+  from: section
+  if: $shown
+  body: $title
+card:
+  title: News
+"#,
+    )
+    .unwrap();
+    let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+    let counter = calls.clone();
+    parser.add_function("audit", move |props, _| {
+        counter.set(counter.get() + 1);
+        Ok(mapping(&[("p", Value::String(format!("{} props", props.as_mapping().map_or(0, |props| props.len()))))]))
+    });
+    assert_eq!(html(&parser.call("page", Value::Null).unwrap()), "<div><p>Guest</p></div>");
+    assert_eq!(calls.get(), 0);
+    let props = mapping(&[("admin", Value::Bool(true))]);
+    assert_eq!(html(&parser.call("page", props).unwrap()), "<div><p>0 props</p></div>");
+    assert_eq!(calls.get(), 1);
+    let props = mapping(&[("shown", Value::Bool(true))]);
+    assert_eq!(html(&parser.call("card", props).unwrap()), "<section>News</section>");
+    assert_eq!(html(&parser.call("card", Value::Null).unwrap()), "");
+}
+
+#[test]
+fn test_each_loop() {
+    let parser = Parser::parse(
+        r#"
+list:
+  from: ul
+  class: list
+  each: $items
+  body:
+    from: li
+    class: ${$index % 2 == 0 && 'even' || 'odd'}
+    data-edge: ${$first || $last}
+    body: $index. $item
+links:
+  each: $pages
+  as: title
+  a: $title
+  href: /$key
+grid:
+  from: table
+  each: $rows
+  as: row
+  body:
+    from: tr
+    each: $row
+    body:
+      td: $row $item
+tagged:
+  from: ul
+  each: $items
+  as: label
+  body:
+    li: $key/$label/$item
+"#,
+    )
+    .unwrap();
+    let render = |name: &str, props: &str| {
+        let props = Parser::parse(props).unwrap().to_value();
+        html(&parser.call(name, props).unwrap())
+    };
+    assert_eq!(
+        render("list", "items: [a, b, c]"),
+        concat!(
+            r#"<ul class="list"><li class="even" data-edge>0. a</li><li class="odd">1. b</li>"#,
+            r#"<li class="even" data-edge>2. c</li></ul>"#,
+        )
+    );
+    assert_eq!(render("list", "items: []"), r#"<ul class="list"></ul>"#);
+    assert_eq!(
+        render("list", "items: [a, b]\nindex: 9\nfirst: false"),
+        r#"<ul class="list" index="9"><li class="even" data-edge>0. a</li><li class="odd" data-edge>1. b</li></ul>"#
+    );
+    assert_eq!(html(&parser.call("list", Value::Null).unwrap()), r#"<ul class="list"></ul>"#);
+    assert_eq!(
+        render("links", "pages: {home: Home, about: About}"),
+        r#"<a href="/home">Home</a><a href="/about">About</a>"#
+    );
+    assert_eq!(
+        render("grid", "rows: [[1, 2], [3]]"),
+        "<table><tr><td>[1, 2] 1</td><td>[1, 2] 2</td></tr><tr><td>[3] 3</td></tr></table>"
+    );
+    assert_eq!(
+        render("tagged", "items: [a, b]\nkey: k\nitem: i"),
+        "<ul><li>k/a/i</li><li>k/b/i</li></ul>"
+    );
+    assert_eq!(
+        render("tagged", "items: {x: a}\nkey: k\nitem: i"),
+        "<ul><li>x/a/i</li></ul>"
+    );
+    assert!(parser.call("list", Parser::parse("items: 3").unwrap().to_value()).is_err());
+}
+
+#[test]
+fn test_nested_props() {
+    let parser = Parser::parse(
+        r#"
+users:
+  from: ul
+  each: $rows
+  as: row
+  body:
+    from: li
+    title: $row.address.city
+    body: "$row.name (${$row.tags[0] || 'none'})"
+owner: $rows[0]
+"#,
+    )
+    .unwrap();
+    let props = Parser::parse(
+        r#"
+rows:
+  - name: Ana
+    address: {city: Lisbon}
+    tags: [admin]
+  - name: Bob
+"#,
+    )
+    .unwrap()
+    .to_value();
+    assert_eq!(
+        html(&parser.call("users", props.clone()).unwrap()),
+        r#"<ul><li title="Lisbon">Ana (admin)</li><li>Bob (none)</li></ul>"#
+    );
+    let owner = parser.call("owner", props).unwrap();
+    assert_eq!(owner.as_mapping().unwrap().get(&Value::String("name".into())), Some(&Value::String("Ana".into())));
+}
+
+#[test]
+fn test_text_after_scalar_prop_is_not_a_path() {
+    let parser = Parser::parse(
+        r#"
+photo:
+  from: img
+  src: /img/$id.png
+download:
+  a: Download $file.pdf
+  href: $file
+"#,
+    )
+    .unwrap();
+    let props = mapping(&[("id", Value::Int(7))]);
+    assert_eq!(html(&parser.call("photo", props).unwrap()), r#"<img src="/img/7.png">"#);
+    assert_eq!(html(&parser.call("photo", Value::Null).unwrap()), r#"<img src="/img/.png">"#);
+    let props = mapping(&[("file", Value::String("report".into()))]);
+    assert_eq!(
+        html(&parser.call("download", props).unwrap()),
+        r#"<a href="report">Download report.pdf</a>"#
+    );
+    assert_eq!(html(&parser.call("download", Value::Null).unwrap()), "<a>Download .pdf</a>");
+}
+
+#[test]
+fn test_prop_fallbacks() {
+    let mut parser = Parser::parse(
+        r#"
+action:
+  from: button
+  class: btn btn-$kind|'primary'
+  tabindex: $tabindex|default(0)
+  title: "${title ?? 'Untitled ' + (kind ?? 'item')}"
+  body: $label|'Click me'
+counter:
+  value: $count|default(0)
+badge:
+  from: span
+  class: badge-$kind|primary
+  body: $label|New
+typo:
+  p: $name|uper(2)
+"#,
+    )
+    .unwrap();
+    parser.set_strict(true);
+    assert_eq!(
+        html(&parser.call("action", Value::Null).unwrap()),
+        r#"<button class="btn btn-primary" tabindex="0" title="Untitled item">Click me</button>"#
+    );
+    let props = Parser::parse("kind: danger\nlabel: Delete\ntitle: Remove").unwrap().to_value();
+    assert_eq!(
+        html(&parser.call("action", props).unwrap()),
+        r#"<button class="btn btn-danger" tabindex="0" title="Remove">Delete</button>"#
+    );
+    let value = |props: Value| {
+        let counter = parser.call("counter", props).unwrap();
+        counter.as_mapping().unwrap()[&Value::String("value".into())].clone()
+    };
+    assert_eq!(value(Value::Null), Value::Int(0));
+    assert_eq!(value(Parser::parse("count: 3").unwrap().to_value()), Value::Int(3));
+    assert_eq!(html(&parser.call("badge", Value::Null).unwrap()), r#"<span class="badge-primary">New</span>"#);
+    let props = Parser::parse("kind: danger\nlabel: Old").unwrap().to_value();
+    assert_eq!(html(&parser.call("badge", props).unwrap()), r#"<span class="badge-danger">Old</span>"#);
+    let error = parser.call("typo", Value::Null).unwrap_err();
+    assert!(matches!(&error, htymlx::Error::Unresolved { props: names, .. } if names == &["name"]));
+    parser.set_strict(false);
+    let error = parser.call("typo", Value::Null).unwrap_err();
+    assert!(error.to_string().contains("Unknown filter `uper`"));
+    parser.add_filter("primary", |value, _| Ok(value));
+    assert_eq!(
+        html(&parser.call("action", Value::Null).unwrap()),
+        r#"<button class="btn btn-primary" tabindex="0" title="Untitled item">Click me</button>"#
+    );
+    parser.add_filter("New", |value, _| Ok(Value::String(format!("{}!", value.as_str().unwrap_or("new")))));
+    assert_eq!(html(&parser.call("badge", Value::Null).unwrap()), r#"<span class="badge-">new!</span>"#);
+}
+
+#[test]
+fn test_prop_filters() {
+    let mut parser = Parser::parse(
+        r#"
+card:
+  from: article
+  data-tags: $tags|json
+  body:
+    - h2: $title|trim|title
+    - p: "$summary|truncate(12, '…') (${tags|len} tags: ${tags|join(', ')})"
+    - a: $title|shout
+      href: /search?q=$title|trim|urlencode
+    - span: $date|date('%e %B %Y')|'No date'
+"#,
+    )
+    .unwrap();
+    parser.add_filter("shout", |value, _| Ok(Value::String(format!("{}!", value.as_str().unwrap_or("").trim().to_uppercase()))));
+    let props = Parser::parse(
+        r#"
+title: " hello world "
+summary: A short story about filters
+tags: [a, b]
+date: 2024-05-01
+"#,
+    )
+    .unwrap()
+    .to_value();
+    assert_eq!(
+        html(&parser.call("card", props).unwrap()),
+        concat!(
+            r#"<article data-tags="[&quot;a&quot;,&quot;b&quot;]"><h2>Hello World</h2>"#,
+            "<p>A short stor… (2 tags: a, b)</p>",
+            r#"<a href="/search?q=hello%20world">HELLO WORLD!</a><span>1 May 2024</span></article>"#,
+        )
+    );
+    let props = Parser::parse("title: x\ntags: []").unwrap().to_value();
+    assert!(html(&parser.call("card", props).unwrap()).ends_with("<span>No date</span></article>"));
+    let parser = Parser::parse("broken:\n  p: $title|truncate").unwrap();
+    assert!(parser.call("broken", Parser::parse("title: x").unwrap().to_value()).is_err());
+}
+
+#[test]
+fn test_props_schema() {
+    let parser = Parser::parse(
+        r#"
+Button:
+  props:
+    size:
+      type: string
+      enum: [small, medium, large]
+      default: medium
+      description: Padding and font size
+    label: {type: string, required: true}
+    count: number
+    type: {type: string, default: button}
+  from: button
+  class: btn-$size
+  body: $label $count
+toolbar:
+  div:
+    - from: Button
+      label: Save
+    - from: Button
+      label: Delete
+      size: $size
+"#,
+    )
+    .unwrap();
+    let call = |name: &str, props: &str| parser.call(name, Parser::parse(props).unwrap().to_value());
+    assert_eq!(html(&call("Button", "label: Save").unwrap()), r#"<button class="btn-medium">Save</button>"#);
+    assert_eq!(
+        html(&call("Button", "label: Send\ntype: submit").unwrap()),
+        r#"<button class="btn-medium" type="submit">Send</button>"#
+    );
+    assert_eq!(
+        html(&call("toolbar", "size: small").unwrap()),
+        r#"<div><button class="btn-medium">Save</button><button class="btn-small">Delete</button></div>"#
+    );
+    let error = call("Button", "label: Save\nsize: larg").unwrap_err();
+    assert!(matches!(error, htymlx::Error::Props { ref component, .. } if component == "Button"));
+    assert_eq!(
+        error.to_string().split(" (").next().unwrap(),
+        "invalid props for `Button`: `size` must be one of `small`, `medium`, `large`, got `larg`"
+    );
+    assert!(call("Button", "size: small").unwrap_err().to_string().contains("`label` is required"));
+    assert!(call("Button", "label: Save\ncount: many").unwrap_err().to_string().contains("`count` must be a number"));
+    assert_eq!(
+        call("Button", "size: larg\ncount: many").unwrap_err().to_string().split(" (").next().unwrap(),
+        concat!(
+            "invalid props for `Button`: `size` must be one of `small`, `medium`, `large`, got `larg`, ",
+            "`label` is required, `count` must be a number, got `many`"
+        )
+    );
+    let error = call("toolbar", "size: huge").unwrap_err();
+    assert_eq!(error.call_stack(), ["toolbar", "Button"]);
+    assert_eq!(html(&call("Button", "label: Save\nid: save").unwrap()), r#"<button class="btn-medium" id="save">Save</button>"#);
+    let props = parser.props("Button").unwrap();
+    assert_eq!(props.len(), 4);
+    assert_eq!(props[0].description.as_deref(), Some("Padding and font size"));
+    assert_eq!(props[2].kind, Some(htymlx::parser::PropType::Number));
+    let mut parser = parser;
+    parser.set_strict(true);
+    let error = parser.call("Button", Parser::parse("label: Save\nid: save\nsise: large").unwrap().to_value()).unwrap_err();
+    assert_eq!(error.to_string().split(" (").next().unwrap(), "invalid props for `Button`: unknown props `id`, `sise`");
+    let props = Parser::parse("label: Save\nfrom: button\nbody: Send").unwrap().to_value();
+    assert_eq!(html(&parser.call("Button", props).unwrap()), r#"<button class="btn-medium">Send</button>"#);
+}
+
+#[test]
+fn test_named_slots() {
+    let parser = Parser::parse(
+        r#"
+Card:
+  props:
+    footer: {type: component, slot: true}
+  from: article
+  class: card
+  body:
+    - from: header
+      body: $slot.header
+    - div: $body
+    - footer: $slot.footer|'No footer'
+title:
+  h2: $text
+page:
+  from: Card
+  slots:
+    header:
+      from: title
+      text: $heading
+    unused: Dropped
+  footer: Bye $name
+  body: Hello $name
+plain:
+  from: Card
+  body: Only body
+"#,
+    )
+    .unwrap();
+    let props = Parser::parse("heading: Welcome\nname: Ana").unwrap().to_value();
+    assert_eq!(
+        html(&parser.call("page", props).unwrap()),
+        r#"<article class="card"><header><h2>Welcome</h2></header><div>Hello Ana</div><footer>Bye Ana</footer></article>"#
+    );
+    assert_eq!(
+        html(&parser.call("plain", Value::Null).unwrap()),
+        r#"<article class="card"><header></header><div>Only body</div><footer>No footer</footer></article>"#
+    );
+    let mut parser = Parser::parse("box:\n  from: div\n  slots:\n    aside: x\n  body: $text").unwrap();
+    parser.set_strict(true);
+    assert_eq!(html(&parser.call("box", Parser::parse("text: hi").unwrap().to_value()).unwrap()), "<div>hi</div>");
+}
+
+#[test]
+fn test_context() {
+    let parser = Parser::parse(
+        r#"
+app:
+  provide:
+    theme: $theme|'light'
+  from: div
+  class: app
+  body:
+    - toolbar
+    - from: section
+      body: $ctx.theme|'none'
+toolbar:
+  from: nav
+  body: badge
+badge:
+  from: span
+  class: badge-$ctx.theme
+  body: ${$ctx.user.name ?? 'Guest'}
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        html(&parser.call("app", Value::Null).unwrap()),
+        r#"<div class="app"><nav><span class="badge-light">Guest</span></nav><section>none</section></div>"#
+    );
+    let context = Parser::parse("user: {name: Ana}\ntheme: blue").unwrap().to_value();
+    let props = Parser::parse("theme: dark").unwrap().to_value();
+    assert_eq!(
+        html(&parser.call_with_context("app", props, context.clone()).unwrap()),
+        r#"<div class="app"><nav><span class="badge-dark">Ana</span></nav><section>blue</section></div>"#
+    );
+    assert_eq!(
+        html(&parser.call_with_context("badge", Value::Null, context).unwrap()),
+        r#"<span class="badge-blue">Ana</span>"#
+    );
+    assert!(parser.call_with_context("badge", Value::Null, Value::Int(1)).is_err());
+}
@@ -0,0 +1,93 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn components_file(name: &str, content: &str) -> String {
+    let path = std::env::temp_dir().join(format!("htymlx-cli-{}-{}.yml", name, std::process::id()));
+    std::fs::write(&path, content).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_render_with_props_from_stdin_and_flags() {
+    let file = components_file("render", "Link:\n  from: a\n  href: $url\n  body: $text\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_htymlx"))
+        .args(["render", &file, "Link", "--props", "-", "--prop", "text=Example"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"url: https://example.com").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        r#"<a href="https://example.com">Example</a>"#
+    );
+}
+
+#[test]
+fn test_render_missing_component_fails() {
+    let file = components_file("missing", "Link:\n  from: a\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_htymlx"))
+        .args(["render", &file, "Button"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("component `Button` not found"));
+}
+
+#[test]
+fn test_render_entry_points_into_shell() {
+    let file = components_file("shell", "$(#root): app\n$(nav .menu): Menu\napp:\n  p: Hello\n");
+    let shell = components_file(
+        "shell-html",
+        r#"<html><body><nav><ul class="menu">...</ul></nav><div id="root">Loading</div></body></html>"#,
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_htymlx"))
+        .args(["render", &file, "--shell", &shell])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("cannot mount `nav .menu`"));
+    std::fs::write(&file, "$(#root): app\n$(ul.menu): Menu\napp:\n  p: Hello\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_htymlx"))
+        .args(["render", &file, "--shell", &shell])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        r#"<html><body><nav><ul class="menu">Menu</ul></nav><div id="root"><p>Hello</p></div></body></html>"#
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_htymlx"))
+        .args(["render", &file, "--shell", &shell, "--pretty"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("`--pretty` cannot be combined with `--shell`"));
+    let output = Command::new(env!("CARGO_BIN_EXE_htymlx"))
+        .args(["render", &file, "--shell", &shell, "--document"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("`--document` cannot be combined with `--shell`"));
+}
+
+#[test]
+fn test_render_pretty_and_document() {
+    let file = components_file("pretty", "List:\n  ul:\n    - li: One\n    - li: Two\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_htymlx"))
+        .args(["render", &file, "List", "--document"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        r#"<!DOCTYPE html><html><head><meta charset="utf-8"></head><body><ul><li>One</li><li>Two</li></ul></body></html>"#
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_htymlx"))
+        .args(["render", &file, "List", "--pretty"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "<ul>\n  <li>One</li>\n  <li>Two</li>\n</ul>\n");
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "server")]
+use htymlx::parser::Parser;
+use htymlx::server::{Response, Server};
+
+#[test]
+fn test_serves_root_entry_as_page_and_components_as_fragments() {
+    let server = Server::new(Parser::load("docs/examples/counter-button.yml").unwrap());
+    let page = server.handle("/", "");
+    assert_eq!(page.status, 200);
+    assert!(page.body.starts_with("<!DOCTYPE html>"), "{}", page.body);
+    assert!(page.body.contains(r#"<div id="root"><button hx-swap="outerHTML" hx-get="/app?body=1">Clicks: 0</button></div>"#), "{}", page.body);
+    assert_eq!(
+        server.handle("/app?body=1", ""),
+        Response { status: 200, body: r#"<button hx-swap="outerHTML" hx-get="/app?body=2">Clicks: 1</button>"#.into() }
+    );
+}
+
+#[test]
+fn test_form_params_and_errors() {
+    let parser = Parser::parse(
+        r#"
+greet:
+  from: p
+  body: Hello, $name! ${$count * 2}
+"#,
+    )
+    .unwrap();
+    let server = Server::new(parser);
+    assert_eq!(server.handle("/greet?count=2", "name=Ada+Lovelace").body, "<p>Hello, Ada Lovelace! 4</p>");
+    assert_eq!(server.handle("/missing", "").status, 404);
+    assert_eq!(
+        server.handle("/greet?count=1&onclick=alert(1)&from=script", "name=Ada&if=false&body=x").body,
+        "<p>Hello, Ada! 2</p>"
+    );
+    let error = server.handle("/greet?count=x", "");
+    assert_eq!(error.status, 500);
+    assert_eq!(error.body, "the component failed to render");
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_serves_sql_components() {
+    let components = std::fs::read_to_string("docs/examples/todo-list.yml").unwrap();
+    let schema = "schema:\n  from: sql\n  query: CREATE TABLE todo_items (description TEXT)\n";
+    let mut parser = Parser::parse(&format!("{}\n{}", components, schema)).unwrap();
+    parser.add_sqlite(":memory:").unwrap();
+    let server = Server::new(parser);
+    let error = server.handle("/add-item", "desc=Milk");
+    assert_eq!(error, Response { status: 500, body: "the component failed to render".into() });
+    assert_eq!(server.handle("/schema", "").status, 200);
+    assert_eq!(server.handle("/add-item", "desc=Milk").body, "<li>Milk</li>");
+    assert_eq!(server.handle("/add-item?desc=Eggs+%26+ham", "").body, "<li>Milk</li><li>Eggs &amp; ham</li>");
+}
+
+#[test]
+fn test_listen_keeps_serving_after_bad_requests() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let parser = Parser::parse("greet:\n  from: p\n  body: Hello, $name!").unwrap();
+        Server::new(parser).listen_on(listener).unwrap();
+    });
+    let send = |request: &[u8]| {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let invalid = send(b"POST /greet HTTP/1.1\r\nHost: x\r\nConnection: close\r\nContent-Length: 2\r\n\r\n\xff\xfe");
+    assert!(invalid.starts_with("HTTP/1.1 400"), "{}", invalid);
+    let large = format!("POST /greet HTTP/1.1\r\nHost: x\r\nConnection: close\r\nContent-Length: {}\r\n\r\n", htymlx::server::MAX_BODY_SIZE + 1);
+    let large = send(large.as_bytes());
+    assert!(large.starts_with("HTTP/1.1 413"), "{}", large);
+    let valid = send(b"GET /greet?name=Ada HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+    assert!(valid.ends_with("<p>Hello, Ada!</p>"), "{}", valid);
+}
@@ -0,0 +1,76 @@
+#![cfg(feature = "sqlite")]
+use htymlx::parser::Parser;
+use htymlx::render::html;
+use rust_yaml::Value;
+
+const SCHEMA: &str = r#"
+schema:
+  from: sql
+  query: >
+    CREATE TABLE users (name TEXT, email TEXT, active INTEGER);
+    INSERT INTO users VALUES ('God', 'god@heaven', 1), ('Adam', 'adam@eden', 1), ('Eve', 'eve@eden', 0);
+    CREATE TABLE todo_items (description TEXT);
+"#;
+
+fn parser(components: &str) -> Parser {
+    let mut parser = Parser::parse(&format!("{}\n{}", SCHEMA, components)).unwrap();
+    parser.add_sqlite(":memory:").unwrap();
+    parser.call("schema", Value::Null).unwrap();
+    parser
+}
+
+#[test]
+fn test_sql_renders_body_per_row_with_bound_props() {
+    let parser = parser(
+        r#"
+app:
+  from: ol
+  body:
+    from: sql
+    query: SELECT name FROM users WHERE active = $active ORDER BY rowid
+    active: $active
+    body:
+      li: $name
+"#,
+    );
+    let props = Parser::parse("active: 1").unwrap().to_value();
+    let component = parser.call("app", props).unwrap();
+    assert_eq!(html(&component), "<ol><li>God</li><li>Adam</li></ol>");
+}
+
+#[test]
+fn test_sql_props_are_never_interpolated() {
+    let parser = parser(&std::fs::read_to_string("docs/examples/todo-list.yml").unwrap());
+    let props = Parser::parse(r#"desc: "x'); DROP TABLE todo_items; --""#).unwrap().to_value();
+    let component = parser.call("add-item", props.clone()).unwrap();
+    assert_eq!(html(&component), "<li>x'); DROP TABLE todo_items; --</li>");
+    let component = parser.call("add-item", props).unwrap();
+    assert_eq!(html(&component), "<li>x'); DROP TABLE todo_items; --</li><li>x'); DROP TABLE todo_items; --</li>");
+}
+
+#[test]
+fn test_sql_rows_applied_to_template() {
+    let parser = parser(&std::fs::read_to_string("tests/data/userListExample.yaml").unwrap());
+    let component = parser.call("$userList", Value::Null).unwrap();
+    assert_eq!(
+        html(&component),
+        concat!(
+            r#"<div class="bg-white shadow-md rounded-lg p-6 max-w-sm"><h2>God</h2><span>god@heaven</span></div>"#,
+            r#"<div class="bg-white shadow-md rounded-lg p-6 max-w-sm"><h2>Adam</h2><span>adam@eden</span></div>"#,
+        )
+    );
+}
+
+#[test]
+fn test_sql_errors_are_reported() {
+    let parser = parser(
+        r#"
+broken:
+  from: sql
+  query: SELECT * FROM missing
+"#,
+    );
+    let error = parser.call("broken", Value::Null).unwrap_err();
+    assert!(error.to_string().starts_with("function `sql` failed: no such table: missing"), "{}", error);
+    assert_eq!(error.call_stack(), ["broken", "sql"]);
+}
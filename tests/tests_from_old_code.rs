@@ -769,7 +769,7 @@ btn:
     let html = html(&component);
     assert_eq!(
         html,
-        r#"<button onclick="alert("Hello")">Hello</button><button onclick="alert("World")">World</button>"#
+        r#"<button onclick="alert(&quot;Hello&quot;)">Hello</button><button onclick="alert(&quot;World&quot;)">World</button>"#
     );
 }
 